@@ -0,0 +1,1429 @@
+use bitcoin::{Amount, BlockHash};
+use bitcoincore_rpc::{Client, Result as ClientResult, RpcApi};
+use bitcoincore_rpc_json::{
+    EstimateMode, GetBlockResult, GetBlockStatsResult, GetChainTipsResultStatus,
+    StringOrStringArray,
+};
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Histogram, Registry,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, TryLockError,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config::{Config, NodeConfig},
+    json::{
+        Deployment, GetBlockTemplateResult, GetBlockchainInfoResult, GetDeploymentInfoResult,
+        GetMiningInfoResult, GetPeerInfoResult, GetTxOutSetInfoResult, IndexInfo, ListBannedResult,
+    },
+    metrics::{
+        BanMetrics, BlockStatsWindowMetrics, BlockTemplateMetrics, BlockTimeMetrics,
+        BlockchainMetrics, ChainTipsMetrics, DeploymentMetrics, ExporterMetrics, HashrateMetrics,
+        LatestBlockMetrics, MempoolFeeMetrics, MempoolMetrics, MiningMetrics, NetTotalsMetrics,
+        NetworkMetrics, PeerDetailMetrics, PeerMetrics, ReorgMetrics, SmartFeeMetrics,
+        StatusMetrics, TxOutSetMetrics,
+    },
+    rpc::RpcClient,
+};
+
+/// Collects the metrics of a bitcoind node, exported under its `node` label.
+///
+/// Each refresh registers a fresh set of metrics in a new registry, so series
+/// bitcoind no longer reports (like expired bans) disappear instead of keeping
+/// their last value.
+#[derive(Clone)]
+pub(crate) struct BitcoinCollector {
+    name: String,
+    config: Arc<Config>,
+    rpc: Arc<RpcClient>,
+    exporter: ExporterMetrics,
+    metric_families: Arc<Mutex<Vec<MetricFamily>>>,
+    /// Held for the whole of a refresh, so the background loop and probes don't overlap
+    refreshing: Arc<Mutex<()>>,
+    /// Whether bitcoind answered the last refresh
+    up: Arc<AtomicBool>,
+    net_bytes: Arc<Mutex<NetBytes>>,
+    chain: Arc<Mutex<ChainState>>,
+    blockstats: Arc<Mutex<BTreeMap<u64, GetBlockStatsResult>>>,
+    /// Dedicated client, so slow `gettxoutsetinfo` calls don't hold up refreshes
+    txoutset_rpc: Arc<RpcClient>,
+    txoutset: Arc<Mutex<Vec<MetricFamily>>>,
+}
+
+/// Timeout of the regular rpc calls
+const RPC_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Maximum block weight according to BIP 141
+const MAX_BLOCK_WEIGHT: f64 = 4_000_000.0;
+
+/// A numeric field of `getblockstats`
+type BlockStat = fn(&GetBlockStatsResult) -> f64;
+
+/// `feerate_percentiles` of `getblockstats`, in sat/vB
+const FEE_RATE_PERCENTILES: [(&str, BlockStat); 5] = [
+    ("10", |s| s.fee_rate_percentiles.fr_10th.to_sat() as f64),
+    ("25", |s| s.fee_rate_percentiles.fr_25th.to_sat() as f64),
+    ("50", |s| s.fee_rate_percentiles.fr_50th.to_sat() as f64),
+    ("75", |s| s.fee_rate_percentiles.fr_75th.to_sat() as f64),
+    ("90", |s| s.fee_rate_percentiles.fr_90th.to_sat() as f64),
+];
+
+/// Stop looking for the fork point of a reorg after this many blocks
+const MAX_REORG_DEPTH: usize = 100;
+
+/// `getblockstats` calls per refresh, so large windows fill over several refreshes instead of
+/// holding up the other metrics
+const MAX_BLOCKSTATS_FETCHES: usize = 20;
+
+/// Best block of the previous refresh, to detect chain reorganizations and time blocks
+struct ChainState {
+    tip: Option<BlockHash>,
+    /// Unix timestamp at which the best block changed, or of the block itself when first seen
+    tip_seen: u64,
+    /// Whether bitcoind was in initial block download at the previous refresh
+    syncing: bool,
+    reorgs: u64,
+    last_reorg_depth: usize,
+    block_intervals: Histogram,
+}
+
+impl Default for ChainState {
+    fn default() -> Self {
+        ChainState {
+            tip: None,
+            tip_seen: 0,
+            syncing: false,
+            reorgs: 0,
+            last_reorg_depth: 0,
+            block_intervals: BlockTimeMetrics::block_intervals().unwrap(),
+        }
+    }
+}
+
+/// Direction and message type of peer traffic
+type MsgKey = (&'static str, String);
+
+/// Per message type traffic, accumulated across refreshes as peers come and go.
+///
+/// Bytes exchanged with a peer after the last refresh preceding its disconnection are not
+/// counted.
+#[derive(Default)]
+struct NetBytes {
+    /// Last per message byte counts of each connected peer, by peer id and connection time as
+    /// bitcoind numbers peers from 0 again after a restart
+    peers: HashMap<(u64, u64), HashMap<MsgKey, u64>>,
+    totals: HashMap<MsgKey, u64>,
+}
+
+impl NetBytes {
+    fn update(&mut self, peers: &[GetPeerInfoResult]) {
+        let mut current = HashMap::new();
+        for peer in peers.iter() {
+            let id = (peer.id, peer.conntime);
+            let last = self.peers.remove(&id).unwrap_or_default();
+            let mut counts = HashMap::new();
+            for (direction, per_msg) in [
+                ("sent", &peer.bytessent_per_msg),
+                ("recv", &peer.bytesrecv_per_msg),
+            ] {
+                for (msg_type, bytes) in per_msg.iter() {
+                    let key = (direction, msg_type.clone());
+                    let delta = bytes.saturating_sub(last.get(&key).copied().unwrap_or_default());
+                    *self.totals.entry(key.clone()).or_default() += delta;
+                    counts.insert(key, *bytes);
+                }
+            }
+            current.insert(id, counts);
+        }
+        self.peers = current;
+    }
+}
+
+impl BitcoinCollector {
+    pub(crate) fn new(node: &NodeConfig, config: Arc<Config>) -> Self {
+        // a utxo set call outlasting its interval would be pointless
+        let txoutset_timeout =
+            Duration::from_secs(config.txoutset_interval.unwrap_or_default()).max(RPC_TIMEOUT);
+        BitcoinCollector {
+            name: node.name.clone(),
+            config,
+            rpc: Arc::new(RpcClient::new(&node.host, node.auth(), RPC_TIMEOUT)),
+            exporter: ExporterMetrics::new(&node.name).unwrap(),
+            metric_families: Default::default(),
+            refreshing: Default::default(),
+            up: Default::default(),
+            net_bytes: Default::default(),
+            chain: Default::default(),
+            blockstats: Default::default(),
+            txoutset_rpc: Arc::new(RpcClient::new(&node.host, node.auth(), txoutset_timeout)),
+            txoutset: Default::default(),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Call a bitcoind rpc method, timing it and counting failures.
+    ///
+    /// A failed call is logged and yields `None` so the remaining metrics are still collected.
+    fn observe<T>(&self, method: &str, call: impl FnOnce() -> ClientResult<T>) -> Option<T> {
+        let timer = self
+            .exporter
+            .rpc_duration
+            .with_label_values(&[method])
+            .start_timer();
+        let result = call();
+        timer.observe_duration();
+        match result {
+            Ok(value) => Some(value),
+            Err(e) => {
+                log::warn!("[{}] {} failed: {}", self.name, method, e);
+                self.exporter.rpc_errors.with_label_values(&[method]).inc();
+                None
+            }
+        }
+    }
+
+    /// New registry labeling its metrics with the node name
+    fn registry(&self) -> Registry {
+        Registry::new_custom(
+            None,
+            Some(HashMap::from([("node".to_owned(), self.name.clone())])),
+        )
+        .unwrap()
+    }
+
+    /// Refresh the metrics of the node, returning whether bitcoind answered.
+    ///
+    /// A refresh already in flight is waited for and its result reused, rather than querying
+    /// bitcoind a second time.
+    ///
+    /// RPC calls are blocking, don't call this from an async context.
+    pub(crate) fn refresh(&self) -> bool {
+        let _refreshing = match self.refreshing.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                drop(self.refreshing.lock().unwrap());
+                return self.up.load(Ordering::Relaxed);
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{}", e),
+        };
+        let registry = self.registry();
+        let status = StatusMetrics::new(&registry).unwrap();
+
+        let up = match self.rpc.get() {
+            Ok(rpc) => self.get_metrics(&rpc, &registry, &status),
+            Err(e) => {
+                log::warn!("[{}] can't connect to bitcoind: {}", self.name, e);
+                false
+            }
+        };
+        status.up.set(if up { 1.0 } else { 0.0 });
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.exporter.last_refresh.set(now.as_secs_f64());
+        *self.metric_families.lock().unwrap() = registry.gather();
+        self.up.store(up, Ordering::Relaxed);
+        up
+    }
+
+    /// Refresh the utxo set statistics, keeping the previous ones when the call fails.
+    ///
+    /// Returns whether bitcoind has a synced coinstatsindex, making `gettxoutsetinfo` cheap.
+    pub(crate) fn refresh_txoutset(&self) -> bool {
+        let rpc = match self.txoutset_rpc.get() {
+            Ok(rpc) => rpc,
+            Err(e) => {
+                log::warn!("[{}] can't connect to bitcoind: {}", self.name, e);
+                return false;
+            }
+        };
+        let indexed = self
+            .observe("getindexinfo", || {
+                rpc.call::<HashMap<String, IndexInfo>>("getindexinfo", &[])
+            })
+            .and_then(|indexes| indexes.get("coinstatsindex").map(|index| index.synced))
+            .unwrap_or_default();
+
+        let hash_type = self.config.txoutset_hash_type.as_str();
+        let Some(txoutset) = self.observe("gettxoutsetinfo", || {
+            rpc.call::<GetTxOutSetInfoResult>("gettxoutsetinfo", &[hash_type.into()])
+        }) else {
+            return indexed;
+        };
+
+        let registry = self.registry();
+        let metrics = TxOutSetMetrics::new(&registry).unwrap();
+        metrics.height.set(txoutset.height as f64);
+        metrics.txouts.set(txoutset.txouts as f64);
+        metrics.bogosize.set(txoutset.bogosize as f64);
+        if let Some(transactions) = txoutset.transactions {
+            TxOutSetMetrics::transactions(&registry)
+                .unwrap()
+                .set(transactions as f64);
+        }
+        if let Some(disk_size) = txoutset.disk_size {
+            TxOutSetMetrics::disk_size(&registry)
+                .unwrap()
+                .set(disk_size as f64);
+        }
+        metrics.total_amount.set(txoutset.total_amount.to_btc());
+        if let Some(total_unspendable_amount) = txoutset.total_unspendable_amount {
+            TxOutSetMetrics::total_unspendable_amount(&registry)
+                .unwrap()
+                .set(total_unspendable_amount.to_btc());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        metrics.last_update.set(now.as_secs_f64());
+        *self.txoutset.lock().unwrap() = registry.gather();
+        indexed
+    }
+
+    /// Collect each rpc group independently, so one failing call doesn't blank the others.
+    ///
+    /// Metrics of a group are only registered once its rpc succeeded, so a failed call
+    /// leaves its series out instead of exporting zeroes.
+    fn get_metrics(&self, rpc: &Client, registry: &Registry, status: &StatusMetrics) -> bool {
+        let networkinfo = self.observe("getnetworkinfo", || rpc.get_network_info());
+        let blockchaininfo = self.observe("getblockchaininfo", || {
+            rpc.call::<GetBlockchainInfoResult>("getblockchaininfo", &[])
+        });
+
+        // don't hammer an unreachable bitcoind with the remaining calls
+        if networkinfo.is_none() && blockchaininfo.is_none() {
+            return false;
+        }
+
+        if let Some(blockchaininfo) = &blockchaininfo {
+            let metrics = BlockchainMetrics::new(registry).unwrap();
+            metrics.blocks.set(blockchaininfo.blocks as f64);
+            metrics.difficulty.set(blockchaininfo.difficulty);
+            metrics.size_on_disk.set(blockchaininfo.size_on_disk as f64);
+            metrics
+                .verification_progress
+                .set(blockchaininfo.verificationprogress);
+            metrics.headers.set(blockchaininfo.headers as f64);
+            metrics
+                .blocks_behind_headers
+                .set(blockchaininfo.headers.saturating_sub(blockchaininfo.blocks) as f64);
+            metrics
+                .initial_block_download
+                .set(blockchaininfo.initialblockdownload as u8 as f64);
+            metrics.pruned.set(blockchaininfo.pruned as u8 as f64);
+            if let Some(prune_height) = blockchaininfo.pruneheight {
+                BlockchainMetrics::prune_height(registry)
+                    .unwrap()
+                    .set(prune_height as f64);
+            }
+            if let Some(prune_target_size) = blockchaininfo.prune_target_size {
+                BlockchainMetrics::prune_target_size(registry)
+                    .unwrap()
+                    .set(prune_target_size as f64);
+            }
+            if let Some(time) = blockchaininfo.time {
+                BlockchainMetrics::time(registry).unwrap().set(time as f64);
+            }
+            metrics.median_time.set(blockchaininfo.mediantime as f64);
+            metrics.chainwork.set(log2_hex(&blockchaininfo.chainwork));
+
+            if let Some(block_info) = self.observe("getblock", || {
+                rpc.get_block_info(&blockchaininfo.bestblockhash)
+            }) {
+                LatestBlockMetrics::timestamp(registry)
+                    .unwrap()
+                    .set(block_info.time as f64);
+                if let Some(median_time) = block_info.mediantime {
+                    LatestBlockMetrics::median_time(registry)
+                        .unwrap()
+                        .set(median_time as f64);
+                }
+
+                let disconnected_from = self.update_tip(
+                    rpc,
+                    registry,
+                    &block_info,
+                    blockchaininfo.initialblockdownload,
+                );
+                let latest_blockstats = self
+                    .get_blockstats_windows(
+                        rpc,
+                        registry,
+                        block_info.height as u64,
+                        disconnected_from,
+                        blockchaininfo.pruneheight,
+                    )
+                    .or_else(|| {
+                        self.observe("getblockstats", || {
+                            rpc.get_block_stats(block_info.height as u64)
+                        })
+                    });
+
+                if let Some(latest_blockstats) = latest_blockstats {
+                    let metrics = LatestBlockMetrics::new(registry).unwrap();
+                    metrics.size.set(latest_blockstats.total_size as f64);
+                    metrics.txs.set(latest_blockstats.txs as f64);
+                    metrics.height.set(latest_blockstats.height as f64);
+                    metrics.weight.set(latest_blockstats.total_weight as f64);
+                    metrics.inputs.set(latest_blockstats.ins as f64);
+                    metrics.outputs.set(latest_blockstats.outs as f64);
+                    metrics.value.set(latest_blockstats.total_out.to_btc());
+                    metrics.fee.set(latest_blockstats.total_fee.to_btc());
+                    for (percentile, field) in FEE_RATE_PERCENTILES {
+                        metrics
+                            .fee_rate_percentile
+                            .with_label_values(&[percentile])
+                            .set(field(&latest_blockstats));
+                    }
+                    metrics
+                        .avg_fee_rate
+                        .set(latest_blockstats.avg_fee_rate.to_sat() as f64);
+                    metrics
+                        .min_fee_rate
+                        .set(latest_blockstats.min_fee_rate.to_sat() as f64);
+                    metrics
+                        .max_fee_rate
+                        .set(latest_blockstats.max_fee_rate.to_sat() as f64);
+                    metrics
+                        .median_fee
+                        .set(latest_blockstats.median_fee.to_btc());
+                    metrics.segwit_txs.set(latest_blockstats.sw_txs as f64);
+                    metrics
+                        .segwit_size
+                        .set(latest_blockstats.sw_total_size as f64);
+                    metrics
+                        .utxo_increase
+                        .set(latest_blockstats.utxo_increase as f64);
+                    metrics
+                        .utxo_size_inc
+                        .set(latest_blockstats.utxo_size_inc as f64);
+                    metrics.subsidy.set(latest_blockstats.subsidy.to_btc());
+                }
+            }
+        }
+
+        if let Some(blockchaininfo) = &blockchaininfo {
+            self.get_deployments(rpc, registry, &blockchaininfo.softforks);
+        }
+
+        if let (Some(networkinfo), Some(blockchaininfo)) = (&networkinfo, &blockchaininfo) {
+            if let Some(uptime) = self.observe("uptime", || rpc.uptime()) {
+                status
+                    .uptime
+                    .with_label_values(&[
+                        &networkinfo.version.to_string(),
+                        &networkinfo.protocol_version.to_string(),
+                        &blockchaininfo.chain,
+                    ])
+                    .set(uptime as f64);
+            }
+        }
+
+        if let Some(networkinfo) = networkinfo {
+            let metrics = NetworkMetrics::new(registry).unwrap();
+            metrics.peers.set(networkinfo.connections as f64);
+
+            if let Some(connections_in) = networkinfo.connections_in {
+                metrics.conn_in.set(connections_in as f64);
+            }
+            if let Some(connections_out) = networkinfo.connections_out {
+                metrics.conn_out.set(connections_out as f64);
+            }
+
+            // bitcoind reports a single string before v28 and an array since
+            let warnings = match networkinfo.warnings {
+                StringOrStringArray::String(value) => vec![value],
+                StringOrStringArray::StringArray(values) => values,
+            };
+            let warnings: Vec<_> = warnings
+                .iter()
+                .map(|warning| warning.trim())
+                .filter(|warning| !warning.is_empty())
+                .collect();
+            metrics.warnings_active.set(warnings.len() as f64);
+            for warning in warnings {
+                metrics.warning_info.with_label_values(&[warning]).set(1.0);
+            }
+
+            for network in networkinfo.networks.iter() {
+                let labels = [network.name.as_str()];
+                metrics
+                    .network_reachable
+                    .with_label_values(&labels)
+                    .set(network.reachable as u8 as f64);
+                metrics
+                    .network_limited
+                    .with_label_values(&labels)
+                    .set(network.limited as u8 as f64);
+                metrics
+                    .network_proxy
+                    .with_label_values(&labels)
+                    .set(!network.proxy.is_empty() as u8 as f64);
+            }
+            for address in networkinfo.local_addresses.iter() {
+                metrics
+                    .local_address_score
+                    .with_label_values(&[&address.address, &address.port.to_string()])
+                    .set(address.score as f64);
+            }
+            metrics.relay_fee.set(networkinfo.relay_fee.to_sat() as f64);
+            metrics
+                .incremental_fee
+                .set(networkinfo.incremental_fee.to_sat() as f64);
+            metrics
+                .local_relay
+                .set(networkinfo.local_relay as u8 as f64);
+            metrics
+                .network_active
+                .set(networkinfo.network_active as u8 as f64);
+            match u64::from_str_radix(&networkinfo.local_services, 16) {
+                Ok(services) => {
+                    for service in service_names(services) {
+                        metrics
+                            .local_service
+                            .with_label_values(&[&service])
+                            .set(1.0);
+                    }
+                }
+                Err(e) => log::warn!(
+                    "[{}] invalid localservices {}: {}",
+                    self.name,
+                    networkinfo.local_services,
+                    e
+                ),
+            }
+        }
+
+        {
+            // families without series are not exported, so failed calls export nothing
+            let metrics = SmartFeeMetrics::new(registry).unwrap();
+            for mode in self.config.fee_modes.iter() {
+                let mode_label = estimate_mode(*mode);
+                for target in self.config.fee_targets.iter() {
+                    let smartfee = match self.observe("estimatesmartfee", || {
+                        rpc.estimate_smart_fee(*target, Some(*mode))
+                    }) {
+                        Some(smartfee) => smartfee,
+                        None => continue,
+                    };
+                    let target = target.to_string();
+                    let labels = [target.as_str(), mode_label];
+                    if let Some(fee_rate) = smartfee.fee_rate {
+                        metrics
+                            .fee_rate
+                            .with_label_values(&labels)
+                            .set(fee_rate.to_sat() as f64);
+                    }
+                    metrics
+                        .blocks
+                        .with_label_values(&labels)
+                        .set(smartfee.blocks as f64);
+                    metrics
+                        .errors
+                        .with_label_values(&labels)
+                        .set(smartfee.errors.map_or(0, |errors| errors.len()) as f64);
+                }
+            }
+        }
+
+        if let Some(hashps) = self.observe("getnetworkhashps", || {
+            rpc.get_network_hash_ps(Some(120), None)
+        }) {
+            let metrics = HashrateMetrics::new(registry).unwrap();
+            metrics.hashps.set(hashps);
+        }
+        if let Some(hashps_1) = self.observe("getnetworkhashps", || {
+            rpc.get_network_hash_ps(Some(1), None)
+        }) {
+            HashrateMetrics::hashps_1(registry).unwrap().set(hashps_1);
+        }
+
+        if let Some(banned) = self.observe("listbanned", || {
+            rpc.call::<Vec<ListBannedResult>>("listbanned", &[])
+        }) {
+            // the registry is rebuilt on each refresh, so lifted bans disappear
+            let metrics = BanMetrics::new(registry).unwrap();
+            metrics.banned_peers.set(banned.len() as f64);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs() as i64;
+            for ban in banned.iter() {
+                // bitcoind only reports a reason before v0.20, an empty label leaves it out
+                let labels = [
+                    ban.address.as_str(),
+                    ban.ban_reason.as_deref().unwrap_or_default(),
+                ];
+                metrics
+                    .ban_created
+                    .with_label_values(&labels)
+                    .set(ban.ban_created as f64);
+                metrics
+                    .banned_until
+                    .with_label_values(&labels)
+                    .set(ban.banned_until as f64);
+                metrics.ban_duration.with_label_values(&labels).set(
+                    ban.ban_duration
+                        .unwrap_or(ban.banned_until.saturating_sub(ban.ban_created))
+                        as f64,
+                );
+                metrics.ban_time_remaining.with_label_values(&labels).set(
+                    ban.time_remaining
+                        .unwrap_or(ban.banned_until as i64 - now)
+                        .max(0) as f64,
+                );
+            }
+        }
+
+        self.get_peers(rpc, registry);
+
+        if self.config.mining {
+            self.get_mining(rpc, registry);
+        }
+
+        if let Some(chaintips) = self.observe("getchaintips", || rpc.get_chain_tips()) {
+            let metrics = ChainTipsMetrics::new(registry).unwrap();
+            metrics.num_chaintips.set(chaintips.len() as f64);
+            for status in [
+                GetChainTipsResultStatus::Active,
+                GetChainTipsResultStatus::ValidFork,
+                GetChainTipsResultStatus::ValidHeaders,
+                GetChainTipsResultStatus::HeadersOnly,
+                GetChainTipsResultStatus::Invalid,
+            ] {
+                let count = chaintips.iter().filter(|tip| tip.status == status).count();
+                metrics
+                    .chaintips
+                    .with_label_values(&[chaintip_status(status)])
+                    .set(count as f64);
+            }
+            let longest_fork = chaintips
+                .iter()
+                .filter(|tip| tip.status != GetChainTipsResultStatus::Active)
+                .max_by_key(|tip| tip.branch_length);
+            let (branch_length, height) =
+                longest_fork.map_or((0, 0), |tip| (tip.branch_length, tip.height));
+            metrics.fork_branch_length.set(branch_length as f64);
+            metrics.fork_height.set(height as f64);
+        }
+
+        let mempool = self.observe("getmempoolinfo", || rpc.get_mempool_info());
+        if let Some(mempool) = &mempool {
+            let metrics = MempoolMetrics::new(registry).unwrap();
+            metrics.bytes.set(mempool.bytes as f64);
+            metrics.size.set(mempool.size as f64);
+            metrics.usage.set(mempool.usage as f64);
+            metrics
+                .unbroadcast
+                .set(mempool.unbroadcast_count.unwrap_or_default() as f64);
+            metrics.max_bytes.set(mempool.max_mempool as f64);
+            metrics.min_fee.set(mempool.mempool_min_fee.to_sat() as f64);
+            metrics
+                .min_relay_tx_fee
+                .set(mempool.min_relay_tx_fee.to_sat() as f64);
+            if let Some(total_fee) = mempool.total_fee {
+                MempoolMetrics::total_fee(registry)
+                    .unwrap()
+                    .set(total_fee.to_btc());
+            }
+            if let Some(incremental_relay_fee) = mempool.incremental_relay_fee {
+                MempoolMetrics::incremental_relay_fee(registry)
+                    .unwrap()
+                    .set(incremental_relay_fee.to_sat() as f64);
+            }
+            if let Some(loaded) = mempool.loaded {
+                MempoolMetrics::loaded(registry)
+                    .unwrap()
+                    .set(loaded as u8 as f64);
+            }
+            if let Some(full_rbf) = mempool.full_rbf {
+                MempoolMetrics::full_rbf(registry)
+                    .unwrap()
+                    .set(full_rbf as u8 as f64);
+            }
+        }
+
+        if self.config.mempool_histogram {
+            self.get_mempool_fees(rpc, registry, mempool.map(|mempool| mempool.size));
+        }
+
+        if let Some(netotals) = self.observe("getnettotals", || rpc.get_net_totals()) {
+            let metrics = NetTotalsMetrics::new(registry).unwrap();
+            metrics
+                .total_bytes_recv
+                .set(netotals.total_bytes_recv as f64);
+            metrics
+                .total_bytes_sent
+                .set(netotals.total_bytes_sent as f64);
+            let upload_target = &netotals.upload_target;
+            metrics
+                .upload_target_timeframe
+                .set(upload_target.time_frame as f64);
+            metrics.upload_target.set(upload_target.target as f64);
+            metrics
+                .upload_target_reached
+                .set(upload_target.target_reached as u8 as f64);
+            metrics
+                .serve_historical_blocks
+                .set(upload_target.serve_historical_blocks as u8 as f64);
+            metrics
+                .bytes_left_in_cycle
+                .set(upload_target.bytes_left_in_cycle as f64);
+            metrics
+                .time_left_in_cycle
+                .set(upload_target.time_left_in_cycle as f64);
+        }
+
+        true
+    }
+
+    /// Compare the best block to the one of the previous refresh; a tip that isn't a descendant
+    /// of the previous one means the blocks from the previous tip down to the fork point were
+    /// disconnected. Returns the lowest disconnected height.
+    fn update_tip(
+        &self,
+        rpc: &Client,
+        registry: &Registry,
+        block_info: &GetBlockResult,
+        initial_block_download: bool,
+    ) -> Option<usize> {
+        let previous_tip = self.chain.lock().unwrap().tip;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        // walk the headers without holding the lock, so a concurrent probe isn't stalled
+        let mut disconnected = Vec::new();
+        if let Some(tip) = previous_tip {
+            if tip != block_info.hash && block_info.previousblockhash != Some(tip) {
+                // blocks out of the active chain have -1 confirmations
+                let mut hash = tip;
+                while disconnected.len() < MAX_REORG_DEPTH {
+                    let Some(header) =
+                        self.observe("getblockheader", || rpc.get_block_header_info(&hash))
+                    else {
+                        break;
+                    };
+                    if header.confirmations >= 0 {
+                        break;
+                    }
+                    disconnected.push((header.height, header.hash));
+                    match header.previous_block_hash {
+                        Some(previous) => hash = previous,
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        let mut chain = self.chain.lock().unwrap();
+        match previous_tip {
+            Some(tip) if tip == block_info.hash => {}
+            Some(_) => {
+                // while syncing the tip moves on every refresh, which says nothing about block
+                // intervals, and neither does the first block after syncing
+                if !initial_block_download {
+                    if !chain.syncing {
+                        chain
+                            .block_intervals
+                            .observe(now.saturating_sub(chain.tip_seen) as f64);
+                    }
+                    chain.tip_seen = now;
+                }
+                if !disconnected.is_empty() {
+                    log::warn!(
+                        "[{}] chain reorganization of {} blocks to {} {}, disconnected: {}",
+                        self.name,
+                        disconnected.len(),
+                        block_info.height,
+                        block_info.hash,
+                        disconnected
+                            .iter()
+                            .map(|(height, hash)| format!("{} {}", height, hash))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    chain.reorgs += 1;
+                    chain.last_reorg_depth = disconnected.len();
+                }
+            }
+            None => {
+                // the arrival of the first block seen is unknown, use its timestamp
+                chain.tip_seen = (block_info.time as u64).min(now);
+            }
+        }
+        chain.tip = Some(block_info.hash);
+        chain.syncing = initial_block_download;
+
+        let metrics = ReorgMetrics::new(registry).unwrap();
+        metrics.reorgs.inc_by(chain.reorgs as f64);
+        metrics.last_reorg_depth.set(chain.last_reorg_depth as f64);
+        let metrics = BlockTimeMetrics::new(registry, &chain.block_intervals).unwrap();
+        metrics
+            .seconds_since_last_block
+            .set(now.saturating_sub(chain.tip_seen) as f64);
+
+        disconnected.last().map(|(height, _)| *height)
+    }
+
+    /// Statistics of the last blocks for each of `blockstats_windows`, fetching only the heights
+    /// missing from the cached window.
+    ///
+    /// Returns the cached statistics of the tip, if any.
+    fn get_blockstats_windows(
+        &self,
+        rpc: &Client,
+        registry: &Registry,
+        tip_height: u64,
+        disconnected_from: Option<usize>,
+        prune_height: Option<u64>,
+    ) -> Option<GetBlockStatsResult> {
+        let windows = &self.config.blockstats_windows;
+        let &size = windows.iter().max()?;
+
+        let missing = trim_window(
+            &mut self.blockstats.lock().unwrap(),
+            tip_height,
+            size,
+            prune_height,
+            disconnected_from.map(|height| height as u64),
+        );
+        // fetch without holding the lock, so a concurrent probe isn't stalled
+        let mut fetched = Vec::new();
+        for height in missing.into_iter().take(MAX_BLOCKSTATS_FETCHES) {
+            match self.observe("getblockstats", || rpc.get_block_stats(height)) {
+                Some(stats) => fetched.push((height, stats)),
+                None => break,
+            }
+        }
+
+        let mut blockstats = self.blockstats.lock().unwrap();
+        blockstats.extend(fetched);
+        let metrics = BlockStatsWindowMetrics::new(registry).unwrap();
+        for window in windows.iter() {
+            let stats: Vec<_> = blockstats
+                .range((tip_height + 1).saturating_sub(*window)..=tip_height)
+                .map(|(_, stats)| stats)
+                .collect();
+            if stats.is_empty() {
+                continue;
+            }
+            let label = window.to_string();
+            let blocks = stats.len() as f64;
+            let sum = |field: BlockStat| stats.iter().map(|s| field(s)).sum::<f64>();
+
+            metrics.blocks.with_label_values(&[&label]).set(blocks);
+            metrics
+                .txs
+                .with_label_values(&[&label])
+                .set(sum(|s| s.txs as f64) / blocks);
+            metrics
+                .avg_fee_rate
+                .with_label_values(&[&label])
+                .set(sum(|s| s.avg_fee_rate.to_sat() as f64) / blocks);
+            for (percentile, field) in FEE_RATE_PERCENTILES {
+                metrics
+                    .fee_rate_percentile
+                    .with_label_values(&[&label, percentile])
+                    .set(sum(field) / blocks);
+            }
+            metrics
+                .weight_utilization
+                .with_label_values(&[&label])
+                .set(sum(|s| s.total_weight as f64) / (blocks * MAX_BLOCK_WEIGHT));
+            // the coinbase is not counted in swtxs
+            let txs = sum(|s| s.txs.saturating_sub(1) as f64);
+            if txs > 0.0 {
+                metrics
+                    .segwit_ratio
+                    .with_label_values(&[&label])
+                    .set(sum(|s| s.sw_txs as f64) / txs);
+            }
+            metrics
+                .utxo_increase
+                .with_label_values(&[&label])
+                .set(sum(|s| s.utxo_increase as f64));
+            metrics
+                .utxo_size_inc
+                .with_label_values(&[&label])
+                .set(sum(|s| s.utxo_size_inc as f64));
+        }
+        blockstats.get(&tip_height).cloned()
+    }
+
+    /// Deployments from `getdeploymentinfo`, unless bitcoind predates it and reports `softforks`
+    fn get_deployments(
+        &self,
+        rpc: &Client,
+        registry: &Registry,
+        softforks: &HashMap<String, Deployment>,
+    ) {
+        let deployments = if softforks.is_empty() {
+            let Some(deploymentinfo) = self.observe("getdeploymentinfo", || {
+                rpc.call::<GetDeploymentInfoResult>("getdeploymentinfo", &[])
+            }) else {
+                return;
+            };
+            deploymentinfo.deployments
+        } else {
+            softforks.clone()
+        };
+
+        let metrics = DeploymentMetrics::new(registry).unwrap();
+        for (name, deployment) in deployments.iter() {
+            let labels = [name.as_str(), deployment.deployment_type.as_str()];
+            metrics
+                .active
+                .with_label_values(&labels)
+                .set(deployment.active as u8 as f64);
+            if let Some(height) = deployment.height {
+                metrics.height.with_label_values(&labels).set(height as f64);
+            }
+
+            let Some(bip9) = &deployment.bip9 else {
+                continue;
+            };
+            metrics
+                .bip9_status
+                .with_label_values(&[name, &bip9.status])
+                .set(1.0);
+            metrics
+                .bip9_since
+                .with_label_values(&[name])
+                .set(bip9.since as f64);
+            if let Some(statistics) = &bip9.statistics {
+                metrics
+                    .bip9_period
+                    .with_label_values(&[name])
+                    .set(statistics.period as f64);
+                if let Some(threshold) = statistics.threshold {
+                    metrics
+                        .bip9_threshold
+                        .with_label_values(&[name])
+                        .set(threshold as f64);
+                }
+                metrics
+                    .bip9_elapsed
+                    .with_label_values(&[name])
+                    .set(statistics.elapsed as f64);
+                metrics
+                    .bip9_count
+                    .with_label_values(&[name])
+                    .set(statistics.count as f64);
+            }
+        }
+    }
+
+    /// Statistics of the block a miner would build, to catch empty or stale templates
+    fn get_mining(&self, rpc: &Client, registry: &Registry) {
+        let start = Instant::now();
+        let template = self.observe("getblocktemplate", || {
+            rpc.call::<GetBlockTemplateResult>(
+                "getblocktemplate",
+                &[serde_json::json!({"rules": ["segwit"]})],
+            )
+        });
+        let generation = start.elapsed();
+        if let Some(template) = &template {
+            let metrics = BlockTemplateMetrics::new(registry).unwrap();
+            let transactions = &template.transactions;
+            metrics.txs.set(transactions.len() as f64);
+            metrics
+                .fees
+                .set(Amount::from_sat(transactions.iter().map(|tx| tx.fee).sum()).to_btc());
+            metrics
+                .weight
+                .set(transactions.iter().map(|tx| tx.weight).sum::<u64>() as f64);
+            metrics
+                .sigops
+                .set(transactions.iter().map(|tx| tx.sigops).sum::<u64>() as f64);
+            metrics
+                .coinbase_value
+                .set(Amount::from_sat(template.coinbasevalue).to_btc());
+            metrics.height.set(template.height as f64);
+            metrics.generation.set(generation.as_secs_f64());
+        }
+
+        if let Some(mininginfo) = self.observe("getmininginfo", || {
+            rpc.call::<GetMiningInfoResult>("getmininginfo", &[])
+        }) {
+            let metrics = MiningMetrics::new(registry).unwrap();
+            metrics.pooled_txs.set(mininginfo.pooledtx as f64);
+            if let Some(current_block_weight) = mininginfo.currentblockweight {
+                MiningMetrics::current_block_weight(registry)
+                    .unwrap()
+                    .set(current_block_weight as f64);
+            }
+            if let Some(current_block_txs) = mininginfo.currentblocktx {
+                MiningMetrics::current_block_txs(registry)
+                    .unwrap()
+                    .set(current_block_txs as f64);
+            }
+            // a failed template leaves the lag out rather than looking up to date
+            if let Some(template) = &template {
+                // a block found between the two calls makes the template look behind until
+                // the next refresh
+                MiningMetrics::template_blocks_behind(registry)
+                    .unwrap()
+                    .set((mininginfo.blocks + 1).saturating_sub(template.height) as f64);
+            }
+        }
+    }
+
+    /// Peer breakdown and, unless `aggregate_peers` is set, per peer metrics
+    fn get_peers(&self, rpc: &Client, registry: &Registry) {
+        let Some(peers) = self.observe("getpeerinfo", || {
+            rpc.call::<Vec<GetPeerInfoResult>>("getpeerinfo", &[])
+        }) else {
+            return;
+        };
+
+        let metrics = PeerMetrics::new(registry).unwrap();
+        let details =
+            (!self.config.aggregate_peers).then(|| PeerDetailMetrics::new(registry).unwrap());
+        for peer in peers.iter() {
+            // fields added in later versions are reported as unknown by older nodes
+            let connection_type = peer.connection_type.as_deref().unwrap_or(if peer.inbound {
+                "inbound"
+            } else {
+                "outbound"
+            });
+            let network = peer.network.as_deref().unwrap_or("unknown");
+            let transport = peer.transport_protocol_type.as_deref().unwrap_or("v1");
+            metrics
+                .peers
+                .with_label_values(&[connection_type, network, transport])
+                .inc();
+            metrics
+                .versions
+                .with_label_values(&[&user_agent_client(&peer.subver)])
+                .inc();
+
+            let Some(details) = &details else {
+                continue;
+            };
+            let labels = [peer.addr.as_str(), connection_type, network];
+            if let Some(pingtime) = peer.pingtime {
+                details.ping.with_label_values(&labels).set(pingtime);
+            }
+            if let Some(minping) = peer.minping {
+                details.min_ping.with_label_values(&labels).set(minping);
+            }
+            details
+                .bytes_sent
+                .with_label_values(&labels)
+                .set(peer.bytessent as f64);
+            details
+                .bytes_recv
+                .with_label_values(&labels)
+                .set(peer.bytesrecv as f64);
+            details
+                .conn_time
+                .with_label_values(&labels)
+                .set(peer.conntime as f64);
+            details
+                .synced_headers
+                .with_label_values(&labels)
+                .set(peer.synced_headers as f64);
+            details
+                .synced_blocks
+                .with_label_values(&labels)
+                .set(peer.synced_blocks as f64);
+        }
+
+        let mut net_bytes = self.net_bytes.lock().unwrap();
+        net_bytes.update(&peers);
+        for ((direction, msg_type), bytes) in net_bytes.totals.iter() {
+            metrics
+                .net_bytes
+                .with_label_values(&[direction, msg_type])
+                .inc_by(*bytes as f64);
+        }
+    }
+    /// Fee rate distribution of the mempool, skipped when it holds more than `mempool_max_txs`
+    /// transactions as `getrawmempool true` returns every entry
+    fn get_mempool_fees(&self, rpc: &Client, registry: &Registry, size: Option<usize>) {
+        let Some(size) = size else {
+            return;
+        };
+        let skipped = MempoolFeeMetrics::skipped(registry).unwrap();
+        if let Some(max_txs) = self.config.mempool_max_txs {
+            if size as u64 > max_txs {
+                log::debug!(
+                    "[{}] mempool holds {} transactions, over mempool_max_txs {}, skipping fee rates",
+                    self.name,
+                    size,
+                    max_txs
+                );
+                skipped.set(1.0);
+                return;
+            }
+        }
+        let Some(entries) = self.observe("getrawmempool", || rpc.get_raw_mempool_verbose()) else {
+            return;
+        };
+
+        let buckets = &self.config.mempool_fee_buckets;
+        let metrics = MempoolFeeMetrics::new(registry).unwrap();
+        let mut counts = vec![(0u64, 0u64); buckets.len() + 1];
+        for entry in entries.values() {
+            // the transaction's own fee rate, regardless of the ancestors or descendants it is
+            // mined with
+            let fee_rate = entry.fees.base.to_sat() as f64 / entry.vsize.max(1) as f64;
+            let bucket = buckets
+                .iter()
+                .position(|bound| fee_rate <= *bound)
+                .unwrap_or(buckets.len());
+            counts[bucket].0 += 1;
+            counts[bucket].1 += entry.vsize;
+        }
+        // cumulative like histogram buckets, but a snapshot of the current mempool
+        let (mut txs, mut vsize) = (0, 0);
+        for (i, (bucket_txs, bucket_vsize)) in counts.iter().enumerate() {
+            txs += bucket_txs;
+            vsize += bucket_vsize;
+            let le = buckets
+                .get(i)
+                .map_or("+Inf".to_owned(), |bound| bound.to_string());
+            metrics.txs.with_label_values(&[&le]).set(txs as f64);
+            metrics.vsize.with_label_values(&[&le]).set(vsize as f64);
+        }
+    }
+}
+
+/// Drop the cached entries out of the window of the last `size` blocks up to `tip_height`, or
+/// disconnected from `disconnected_from` on, and return the heights missing from it, newest
+/// first so a failure only shortens the window.
+fn trim_window<T>(
+    cache: &mut BTreeMap<u64, T>,
+    tip_height: u64,
+    size: u64,
+    prune_height: Option<u64>,
+    disconnected_from: Option<u64>,
+) -> Vec<u64> {
+    // blocks below the prune height have no undo data left to compute statistics from
+    let first = (tip_height + 1)
+        .saturating_sub(size)
+        .max(prune_height.unwrap_or_default());
+    if let Some(height) = disconnected_from {
+        cache.split_off(&height);
+    }
+    cache.split_off(&(tip_height + 1));
+    *cache = cache.split_off(&first);
+    (first..=tip_height)
+        .rev()
+        .filter(|height| !cache.contains_key(height))
+        .collect()
+}
+
+/// Base 2 logarithm of a big endian hex encoded number, like `chainwork`
+fn log2_hex(hex: &str) -> f64 {
+    hex.chars()
+        .filter_map(|digit| digit.to_digit(16))
+        .fold(0.0, |value, digit| value * 16.0 + digit as f64)
+        .log2()
+}
+
+/// Label of an `estimatesmartfee` mode
+fn estimate_mode(mode: EstimateMode) -> &'static str {
+    match mode {
+        EstimateMode::Unset => "unset",
+        EstimateMode::Economical => "economical",
+        EstimateMode::Conservative => "conservative",
+    }
+}
+
+/// Status as reported by `getchaintips`
+fn chaintip_status(status: GetChainTipsResultStatus) -> &'static str {
+    match status {
+        GetChainTipsResultStatus::Active => "active",
+        GetChainTipsResultStatus::ValidFork => "valid-fork",
+        GetChainTipsResultStatus::ValidHeaders => "valid-headers",
+        GetChainTipsResultStatus::HeadersOnly => "headers-only",
+        GetChainTipsResultStatus::Invalid => "invalid",
+    }
+}
+
+/// Client and major version of a peer user agent, like `Satoshi:27` or `Satoshi:0.21`.
+///
+/// Peers choose their user agent, so only known clients are kept, with their version dropped when
+/// implausible, and anything else is `other` to bound the label cardinality.
+fn user_agent_client(subver: &str) -> String {
+    const CLIENTS: [&str; 5] = ["Satoshi", "Knots", "btcd", "bcoin", "libbitcoin"];
+    // forks append their name to the one of the client they derive from
+    let Some((name, version)) = subver
+        .split('/')
+        .filter_map(|component| component.split_once(':'))
+        .filter(|(name, _)| CLIENTS.contains(name))
+        .last()
+    else {
+        return "other".to_owned();
+    };
+    let mut numbers = version.split('.').map(|number| number.parse::<u32>().ok());
+    match (numbers.next().flatten(), numbers.next().flatten()) {
+        (Some(0), Some(minor)) if minor < 100 => format!("{}:0.{}", name, minor),
+        (Some(major), _) if (1..100).contains(&major) => format!("{}:{}", name, major),
+        _ => name.to_owned(),
+    }
+}
+
+/// Names of the service bits set in `services`, as reported in `localservicesnames`
+fn service_names(services: u64) -> Vec<String> {
+    const SERVICES: [(u32, &str); 7] = [
+        (0, "NETWORK"),
+        (1, "GETUTXO"),
+        (2, "BLOOM"),
+        (3, "WITNESS"),
+        (6, "COMPACT_FILTERS"),
+        (10, "NETWORK_LIMITED"),
+        (11, "P2P_V2"),
+    ];
+    (0..64)
+        .filter(|bit| services & (1 << bit) != 0)
+        .map(
+            |bit| match SERVICES.iter().find(|(service_bit, _)| *service_bit == bit) {
+                Some((_, name)) => name.to_string(),
+                None => format!("UNKNOWN[2^{}]", bit),
+            },
+        )
+        .collect()
+}
+
+impl Collector for BitcoinCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        // bitcoind metrics are rebuilt on each refresh, only the exporter self-metrics are stable
+        self.exporter.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut metric_families = self.exporter.collect();
+        metric_families.extend(self.metric_families.lock().unwrap().iter().cloned());
+        // stale utxo set statistics of a down node would hide it is down
+        if self.up.load(Ordering::Relaxed) {
+            metric_families.extend(self.txoutset.lock().unwrap().iter().cloned());
+        }
+        metric_families
+    }
+}
+
+/// Refresh `collector` on the blocking thread pool and gather its metrics in a dedicated registry.
+pub(crate) async fn probe(collector: BitcoinCollector) -> Vec<MetricFamily> {
+    let registry = Registry::new();
+    registry.register(Box::new(collector.clone())).unwrap();
+    let name = collector.name.clone();
+    if let Err(e) = tokio::task::spawn_blocking(move || collector.refresh()).await {
+        log::error!("[{}] probe task failed: {}", name, e);
+    }
+    registry.gather()
+}
+
+/// Refresh `collector` every `interval`.
+///
+/// RPC calls are blocking, so they run on the blocking thread pool to keep the
+/// http server responsive while bitcoind is slow to answer. Each node runs its own
+/// loop so a slow node doesn't delay the others.
+pub(crate) async fn run(collector: BitcoinCollector, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let task_collector = collector.clone();
+        if let Err(e) = tokio::task::spawn_blocking(move || task_collector.refresh()).await {
+            log::error!("[{}] metrics refresh task failed: {}", collector.name, e);
+        }
+    }
+}
+
+/// Refresh the utxo set statistics of `collector` every `interval`, or every `refresh_interval`
+/// while a synced coinstatsindex makes them cheap to compute.
+pub(crate) async fn run_txoutset(
+    collector: BitcoinCollector,
+    refresh_interval: Duration,
+    interval: Duration,
+) {
+    loop {
+        let task_collector = collector.clone();
+        let indexed =
+            match tokio::task::spawn_blocking(move || task_collector.refresh_txoutset()).await {
+                Ok(indexed) => indexed,
+                Err(e) => {
+                    log::error!("[{}] utxo set refresh task failed: {}", collector.name, e);
+                    false
+                }
+            };
+        tokio::time::sleep(if indexed {
+            refresh_interval.min(interval)
+        } else {
+            interval
+        })
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(id: u64, conntime: u64, sent: u64, recv: u64) -> GetPeerInfoResult {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "addr": "127.0.0.1:8333",
+            "bytessent": sent,
+            "bytesrecv": recv,
+            "conntime": conntime,
+            "subver": "/Satoshi:28.0.0/",
+            "inbound": false,
+            "synced_headers": 0,
+            "synced_blocks": 0,
+            "bytessent_per_msg": { "inv": sent },
+            "bytesrecv_per_msg": { "inv": recv },
+        }))
+        .unwrap()
+    }
+
+    fn totals(net_bytes: &NetBytes) -> (u64, u64) {
+        let total = |direction| net_bytes.totals[&(direction, "inv".to_string())];
+        (total("sent"), total("recv"))
+    }
+
+    #[test]
+    fn net_bytes_counts_deltas() {
+        let mut net_bytes = NetBytes::default();
+        net_bytes.update(&[peer(0, 100, 10, 20)]);
+        assert_eq!(totals(&net_bytes), (10, 20));
+        net_bytes.update(&[peer(0, 100, 15, 30), peer(1, 110, 5, 5)]);
+        assert_eq!(totals(&net_bytes), (20, 35));
+    }
+
+    #[test]
+    fn net_bytes_keeps_disconnected_peers_traffic() {
+        let mut net_bytes = NetBytes::default();
+        net_bytes.update(&[peer(0, 100, 10, 20), peer(1, 110, 5, 5)]);
+        net_bytes.update(&[peer(1, 110, 6, 6)]);
+        assert_eq!(totals(&net_bytes), (16, 26));
+        assert_eq!(net_bytes.peers.len(), 1);
+    }
+
+    #[test]
+    fn net_bytes_ignores_counters_going_down() {
+        let mut net_bytes = NetBytes::default();
+        net_bytes.update(&[peer(0, 100, 10, 20)]);
+        net_bytes.update(&[peer(0, 100, 5, 5)]);
+        assert_eq!(totals(&net_bytes), (10, 20));
+        net_bytes.update(&[peer(0, 100, 7, 5)]);
+        assert_eq!(totals(&net_bytes), (12, 20));
+    }
+
+    #[test]
+    fn net_bytes_counts_reused_peer_ids_after_restart() {
+        let mut net_bytes = NetBytes::default();
+        net_bytes.update(&[peer(0, 100, 1000, 1000)]);
+        // bitcoind restarted and numbers its new first peer 0 again
+        net_bytes.update(&[peer(0, 200, 300, 400)]);
+        assert_eq!(totals(&net_bytes), (1300, 1400));
+    }
+
+    fn window(heights: impl IntoIterator<Item = u64>) -> BTreeMap<u64, ()> {
+        heights.into_iter().map(|height| (height, ())).collect()
+    }
+
+    #[test]
+    fn trim_window_fills_newest_first() {
+        let mut cache = window([]);
+        assert_eq!(trim_window(&mut cache, 10, 3, None, None), [10, 9, 8]);
+        let mut cache = window(8..=10);
+        assert_eq!(trim_window(&mut cache, 12, 3, None, None), [12, 11]);
+        assert_eq!(cache, window([10]));
+        // near genesis
+        let mut cache = window([]);
+        assert_eq!(trim_window(&mut cache, 1, 5, None, None), [1, 0]);
+    }
+
+    #[test]
+    fn trim_window_drops_disconnected_blocks() {
+        let mut cache = window(5..=10);
+        assert_eq!(trim_window(&mut cache, 11, 6, None, Some(9)), [11, 10, 9]);
+        assert_eq!(cache, window(6..=8));
+    }
+
+    #[test]
+    fn trim_window_stops_at_prune_height() {
+        let mut cache = window(5..=10);
+        assert!(trim_window(&mut cache, 10, 6, Some(8), None).is_empty());
+        assert_eq!(cache, window(8..=10));
+        let mut cache = window([]);
+        assert_eq!(trim_window(&mut cache, 10, 6, Some(8), None), [10, 9, 8]);
+    }
+
+    #[test]
+    fn trim_window_follows_tip_going_backwards() {
+        let mut cache = window(5..=10);
+        assert_eq!(trim_window(&mut cache, 8, 6, None, None), [4, 3]);
+        assert_eq!(cache, window(5..=8));
+    }
+
+    #[test]
+    fn service_names_of_known_bits() {
+        assert!(service_names(0).is_empty());
+        // as reported by a v27 listening node
+        assert_eq!(
+            service_names(0xc09),
+            ["NETWORK", "WITNESS", "NETWORK_LIMITED", "P2P_V2"]
+        );
+    }
+
+    #[test]
+    fn service_names_of_unknown_bits() {
+        assert_eq!(
+            service_names(1 << 5 | 1 << 63),
+            ["UNKNOWN[2^5]", "UNKNOWN[2^63]"]
+        );
+    }
+
+    #[test]
+    fn user_agent_client_keeps_major_version() {
+        assert_eq!(user_agent_client("/Satoshi:27.1.0/"), "Satoshi:27");
+        assert_eq!(user_agent_client("/Satoshi:0.21.1/"), "Satoshi:0.21");
+        assert_eq!(
+            user_agent_client("/Satoshi:27.1.0/Knots:20240801/"),
+            "Knots"
+        );
+        assert_eq!(
+            user_agent_client("/btcwire:0.5.0/btcd:0.24.2/"),
+            "btcd:0.24"
+        );
+    }
+
+    #[test]
+    fn user_agent_client_bounds_arbitrary_values() {
+        assert_eq!(user_agent_client(""), "other");
+        assert_eq!(user_agent_client("/Satoshi/"), "other");
+        assert_eq!(user_agent_client("/MyScanner:1.0/"), "other");
+        assert_eq!(user_agent_client("/Satoshi:123456.0.0/"), "Satoshi");
+        assert_eq!(user_agent_client("/Satoshi:0.123456/"), "Satoshi");
+        assert_eq!(user_agent_client("/Satoshi:evil/"), "Satoshi");
+    }
+
+    #[test]
+    fn log2_hex_of_chainwork() {
+        assert_eq!(log2_hex("1"), 0.0);
+        assert_eq!(log2_hex("0100"), 8.0);
+        assert_eq!(log2_hex("000c"), 12f64.log2());
+        // over 64 bits, like the chainwork of mainnet
+        assert_eq!(log2_hex(&format!("1{}", "0".repeat(23))), 92.0);
+        assert_eq!(log2_hex("0"), f64::NEG_INFINITY);
+    }
+}
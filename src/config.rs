@@ -1,6 +1,7 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
+use bitcoincore_rpc::{json::EstimateMode, Auth};
 use serde::Deserialize;
-use std::fs::File;
+use std::{collections::HashSet, fs::File, path::PathBuf};
 
 fn default_host() -> String {
     "http://127.0.0.1:8332".to_owned()
@@ -10,28 +11,295 @@ fn default_bind() -> String {
     "127.0.0.1:9898".to_owned()
 }
 
-/// Config file
+fn default_refresh_interval() -> u64 {
+    15
+}
+
+fn default_fee_targets() -> Vec<u16> {
+    vec![2, 3, 5, 20]
+}
+
+fn default_fee_modes() -> Vec<EstimateMode> {
+    vec![EstimateMode::Economical, EstimateMode::Conservative]
+}
+
+fn default_mempool_fee_buckets() -> Vec<f64> {
+    vec![
+        1.0, 2.0, 3.0, 5.0, 8.0, 10.0, 15.0, 20.0, 30.0, 50.0, 75.0, 100.0, 150.0, 200.0, 300.0,
+        500.0, 1000.0,
+    ]
+}
+
+/// `hash_type` of `gettxoutsetinfo`
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TxOutSetHashType {
+    #[default]
+    None,
+    Muhash,
+}
+
+impl TxOutSetHashType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxOutSetHashType::None => "none",
+            TxOutSetHashType::Muhash => "muhash",
+        }
+    }
+}
+
+/// Node name used when the config file describes a single node
+const DEFAULT_NODE: &str = "default";
+
+/// Bitcoind rpc target
 #[derive(Deserialize, Clone)]
-pub struct Config {
+pub struct NodeConfig {
+    /// node name, exported as the `node` label
+    pub name: String,
     /// bitcoin rpc host
     #[serde(default = "default_host")]
     pub host: String,
     /// rpc user
-    pub user: String,
+    pub user: Option<String>,
     /// rpc password
-    pub password: String,
+    pub password: Option<String>,
+    /// bitcoind cookie file, used instead of user and password
+    pub cookie_file: Option<PathBuf>,
+}
+
+impl NodeConfig {
+    fn validate(&self) -> Result<()> {
+        ensure!(!self.name.is_empty(), "node name can't be empty");
+        match (&self.cookie_file, &self.user, &self.password) {
+            (Some(_), None, None) | (None, Some(_), Some(_)) => Ok(()),
+            (Some(_), _, _) => bail!(
+                "{}: cookie_file can't be used with user or password",
+                self.name
+            ),
+            (None, _, _) => bail!(
+                "{}: either cookie_file or both user and password are required",
+                self.name
+            ),
+        }
+    }
+
+    /// Rpc authentication method, validated by `Config::read`
+    pub fn auth(&self) -> Auth {
+        match (&self.cookie_file, &self.user, &self.password) {
+            (Some(cookie_file), _, _) => Auth::CookieFile(cookie_file.clone()),
+            (None, Some(user), Some(password)) => Auth::UserPass(user.clone(), password.clone()),
+            _ => Auth::None,
+        }
+    }
+}
+
+/// Config file
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    /// bitcoin rpc host, when exporting a single node
+    pub host: Option<String>,
+    /// rpc user, when exporting a single node
+    pub user: Option<String>,
+    /// rpc password, when exporting a single node
+    pub password: Option<String>,
+    /// bitcoind cookie file, when exporting a single node
+    pub cookie_file: Option<PathBuf>,
+    /// bitcoind rpc targets
+    #[serde(default)]
+    pub nodes: Vec<NodeConfig>,
     /// bind to addr:port
     #[serde(default = "default_bind")]
     pub bind: String,
+    /// seconds between two metrics refreshes
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+    /// confirmation targets of smart fee estimates, in blocks
+    #[serde(default = "default_fee_targets")]
+    pub fee_targets: Vec<u16>,
+    /// smart fee estimate modes (ECONOMICAL, CONSERVATIVE)
+    #[serde(default = "default_fee_modes")]
+    pub fee_modes: Vec<EstimateMode>,
+    /// export the mempool fee rate distribution from `getrawmempool true`
+    #[serde(default)]
+    pub mempool_histogram: bool,
+    /// upper bounds of the mempool fee rate buckets, in sat/vB
+    #[serde(default = "default_mempool_fee_buckets")]
+    pub mempool_fee_buckets: Vec<f64>,
+    /// skip the mempool fee rate distribution above this number of transactions, no limit by default
+    pub mempool_max_txs: Option<u64>,
+    /// only export peer counts, without per peer metrics
+    #[serde(default)]
+    pub aggregate_peers: bool,
+    /// export block template metrics from `getblocktemplate` and `getmininginfo`
+    #[serde(default)]
+    pub mining: bool,
+    /// sizes in blocks of the rolling windows of block statistics, none by default
+    #[serde(default)]
+    pub blockstats_windows: Vec<u64>,
+    /// seconds between two `gettxoutsetinfo` calls, disabled by default
+    pub txoutset_interval: Option<u64>,
+    /// hash of the utxo set computed by `gettxoutsetinfo` (none, muhash)
+    #[serde(default)]
+    pub txoutset_hash_type: TxOutSetHashType,
 }
 
 impl Config {
     pub fn read(config: &str) -> Result<Config> {
         // open configuration file
-        let file = File::open(&config).with_context(|| format!("Can't open {}", &config))?;
+        let file = File::open(config).with_context(|| format!("Can't open {}", &config))?;
         // deserialize configuration
         let config: Config =
             serde_yaml::from_reader(file).with_context(|| format!("Can't read {}", &config))?;
-        Ok(config)
+        config.validate()
+    }
+
+    /// Check the settings and move top level rpc parameters to a single default node
+    fn validate(mut self) -> Result<Config> {
+        ensure!(
+            self.refresh_interval > 0,
+            "refresh_interval must be greater than 0"
+        );
+        ensure!(
+            self.fee_targets
+                .iter()
+                .all(|target| (1..=1008).contains(target)),
+            "fee_targets must be between 1 and 1008 blocks"
+        );
+        ensure!(
+            !self.mempool_fee_buckets.is_empty()
+                && self.mempool_fee_buckets.windows(2).all(|w| w[0] < w[1]),
+            "mempool_fee_buckets must be a non empty list of increasing fee rates"
+        );
+        ensure!(
+            !self.blockstats_windows.contains(&0),
+            "blockstats_windows must be greater than 0"
+        );
+        ensure!(
+            self.txoutset_interval != Some(0),
+            "txoutset_interval must be greater than 0"
+        );
+
+        // top level rpc parameters describe a single node
+        let single = self.host.is_some()
+            || self.user.is_some()
+            || self.password.is_some()
+            || self.cookie_file.is_some();
+        if self.nodes.is_empty() {
+            self.nodes.push(NodeConfig {
+                name: DEFAULT_NODE.to_owned(),
+                host: self.host.take().unwrap_or_else(default_host),
+                user: self.user.take(),
+                password: self.password.take(),
+                cookie_file: self.cookie_file.take(),
+            });
+        } else if single {
+            bail!(
+                "host, user, password and cookie_file must be set in each node when nodes is used"
+            );
+        }
+
+        let mut names = HashSet::new();
+        for node in self.nodes.iter() {
+            node.validate()?;
+            ensure!(
+                names.insert(&node.name),
+                "duplicate node name {}",
+                node.name
+            );
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Result<Config> {
+        serde_yaml::from_str::<Config>(yaml)?.validate()
+    }
+
+    #[test]
+    fn top_level_rpc_parameters_make_a_default_node() {
+        let config = parse("host: http://10.0.0.1:8332\nuser: u\npassword: p\n").unwrap();
+        assert_eq!(config.nodes.len(), 1);
+        assert_eq!(config.nodes[0].name, DEFAULT_NODE);
+        assert_eq!(config.nodes[0].host, "http://10.0.0.1:8332");
+    }
+
+    #[test]
+    fn nodes() {
+        let config = parse(
+            "nodes:
+  - name: a
+    user: u
+    password: p
+  - name: b
+    host: http://10.0.0.2:8332
+    cookie_file: /var/lib/bitcoind/.cookie
+",
+        )
+        .unwrap();
+        assert_eq!(config.nodes.len(), 2);
+        assert_eq!(config.nodes[0].host, default_host());
+        assert!(matches!(config.nodes[1].auth(), Auth::CookieFile(_)));
+    }
+
+    #[test]
+    fn cookie_file_with_user() {
+        assert!(parse("cookie_file: /tmp/.cookie\nuser: u\n").is_err());
+        assert!(parse(
+            "nodes:
+  - name: a
+    cookie_file: /tmp/.cookie
+    password: p
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn missing_credentials() {
+        assert!(parse("host: http://10.0.0.1:8332\n").is_err());
+        assert!(parse("user: u\n").is_err());
+    }
+
+    #[test]
+    fn top_level_rpc_parameters_with_nodes() {
+        assert!(parse(
+            "user: u
+nodes:
+  - name: a
+    user: u
+    password: p
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn duplicate_node_names() {
+        assert!(parse(
+            "nodes:
+  - name: a
+    user: u
+    password: p
+  - name: a
+    cookie_file: /tmp/.cookie
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn empty_node_name() {
+        assert!(parse(
+            "nodes:
+  - name: ''
+    user: u
+    password: p
+"
+        )
+        .is_err());
     }
 }
@@ -0,0 +1,193 @@
+//! Rpc results missing or incomplete in `bitcoincore_rpc_json`
+
+use bitcoin::{Amount, BlockHash};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Models the result of "getblockchaininfo"
+///
+/// `bitcoincore_rpc_json::GetBlockchainInfoResult` lacks `time` and only knows the chains of its
+/// `bitcoin` version.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct GetBlockchainInfoResult {
+    /// Current network name: main, test, testnet4, signet, regtest
+    pub chain: String,
+    pub blocks: u64,
+    pub headers: u64,
+    pub bestblockhash: BlockHash,
+    pub difficulty: f64,
+    /// Timestamp of the best block
+    /// Added in Bitcoin Core v23
+    pub time: Option<u64>,
+    pub mediantime: u64,
+    pub verificationprogress: f64,
+    pub initialblockdownload: bool,
+    /// Total amount of work in the active chain, hex encoded
+    pub chainwork: String,
+    pub size_on_disk: u64,
+    pub pruned: bool,
+    /// Lowest height of complete blocks stored, if pruned
+    pub pruneheight: Option<u64>,
+    /// Target size used by pruning, if automatic pruning is enabled
+    pub prune_target_size: Option<u64>,
+    /// Status of softforks
+    /// Removed in Bitcoin Core v23 in favor of "getdeploymentinfo"
+    #[serde(default)]
+    pub softforks: HashMap<String, Deployment>,
+}
+
+/// Models the result of "getdeploymentinfo", added in Bitcoin Core v23
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct GetDeploymentInfoResult {
+    pub deployments: HashMap<String, Deployment>,
+}
+
+/// Models a deployment of "getdeploymentinfo" or a softfork of "getblockchaininfo"
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct Deployment {
+    /// One of buried, bip9
+    #[serde(rename = "type")]
+    pub deployment_type: String,
+    pub active: bool,
+    /// Height of the first block the rules apply to, when active or buried
+    pub height: Option<u64>,
+    /// Status of bip9 deployments
+    pub bip9: Option<Bip9Info>,
+}
+
+/// Models the "bip9" field of a deployment
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct Bip9Info {
+    /// One of defined, started, locked_in, active, failed
+    pub status: String,
+    /// Height of the first block the status applies to
+    pub since: u64,
+    /// Signalling statistics of the current period, while started or locked_in
+    pub statistics: Option<Bip9Statistics>,
+}
+
+/// Models the "statistics" field of a bip9 deployment
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct Bip9Statistics {
+    /// Length in blocks of the signalling period
+    pub period: u64,
+    /// Number of signalling blocks required for activation, missing once locked in
+    pub threshold: Option<u64>,
+    /// Number of blocks elapsed since the beginning of the current period
+    pub elapsed: u64,
+    /// Number of signalling blocks in the current period
+    pub count: u64,
+}
+
+/// Models an entry of "listbanned"
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct ListBannedResult {
+    pub address: String,
+    pub ban_created: u64,
+    pub banned_until: u64,
+    /// Ban duration in seconds
+    /// Added in Bitcoin Core v22
+    pub ban_duration: Option<u64>,
+    /// Time remaining until the ban expires, in seconds
+    /// Added in Bitcoin Core v22
+    pub time_remaining: Option<i64>,
+    /// Why the address was banned
+    /// Removed in Bitcoin Core v0.20, where only manual bans remain
+    pub ban_reason: Option<String>,
+}
+
+/// Models an entry of "getpeerinfo"
+///
+/// `bitcoincore_rpc_json::GetPeerInfoResult` requires fields removed from recent Bitcoin Core
+/// versions and lacks the v2 transport ones.
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct GetPeerInfoResult {
+    /// Peer index, unique until bitcoind restarts
+    pub id: u64,
+    pub addr: String,
+    /// Network of the peer: ipv4, ipv6, onion, i2p, cjdns, not_publicly_routable
+    /// Added in Bitcoin Core v0.21
+    pub network: Option<String>,
+    pub bytessent: u64,
+    pub bytesrecv: u64,
+    pub conntime: u64,
+    /// Last ping time in seconds, missing until the first pong
+    pub pingtime: Option<f64>,
+    /// Minimum observed ping time in seconds
+    pub minping: Option<f64>,
+    pub subver: String,
+    pub inbound: bool,
+    pub synced_headers: i64,
+    pub synced_blocks: i64,
+    /// Type of the connection, e.g. outbound-full-relay, block-relay-only, inbound
+    /// Added in Bitcoin Core v0.21
+    pub connection_type: Option<String>,
+    /// Transport protocol of the connection: detecting, v1 or v2
+    /// Added in Bitcoin Core v26
+    pub transport_protocol_type: Option<String>,
+    /// Total bytes sent to the peer by message type
+    #[serde(default)]
+    pub bytessent_per_msg: HashMap<String, u64>,
+    /// Total bytes received from the peer by message type
+    #[serde(default)]
+    pub bytesrecv_per_msg: HashMap<String, u64>,
+}
+
+/// Models the result of "getblocktemplate", restricted to the template statistics
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct GetBlockTemplateResult {
+    pub transactions: Vec<BlockTemplateTransaction>,
+    /// Maximum allowable input to the coinbase transaction, in satoshis
+    pub coinbasevalue: u64,
+    pub height: u64,
+}
+
+/// Models an entry of the "transactions" field of "getblocktemplate"
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct BlockTemplateTransaction {
+    /// Fee in satoshis
+    pub fee: u64,
+    pub sigops: u64,
+    pub weight: u64,
+}
+
+/// Models the result of "getmininginfo"
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct GetMiningInfoResult {
+    pub blocks: u64,
+    /// Weight of the last assembled block
+    /// Only reported after a block template was generated
+    pub currentblockweight: Option<u64>,
+    /// Number of transactions of the last assembled block
+    /// Only reported after a block template was generated
+    pub currentblocktx: Option<u64>,
+    pub pooledtx: u64,
+}
+
+/// Models an entry of "getindexinfo", added in Bitcoin Core v0.21
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct IndexInfo {
+    pub synced: bool,
+}
+
+/// Models the result of "gettxoutsetinfo"
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct GetTxOutSetInfoResult {
+    pub height: u64,
+    /// Number of unspent transaction outputs
+    pub txouts: u64,
+    /// Database independent metric of the utxo set size
+    pub bogosize: u64,
+    /// Number of transactions with unspent outputs
+    /// Not reported when using coinstatsindex
+    pub transactions: Option<u64>,
+    /// Estimated size of the chainstate on disk
+    /// Not reported when using coinstatsindex
+    pub disk_size: Option<u64>,
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    pub total_amount: Amount,
+    /// Total amount of coins permanently excluded from the utxo set
+    /// Only reported when using coinstatsindex
+    #[serde(default, with = "bitcoin::amount::serde::as_btc::opt")]
+    pub total_unspendable_amount: Option<Amount>,
+}
@@ -1,47 +1,82 @@
 mod args;
+mod collector;
 mod config;
+mod json;
 mod metrics;
+mod rpc;
 mod serve;
 
-use bitcoincore_rpc::{Auth, Client, Error};
 use hyper::{
     server::conn::AddrStream,
     service::{make_service_fn, service_fn},
     Server,
 };
-use std::sync::Arc;
+use prometheus::Registry;
+use std::{convert::Infallible, sync::Arc, time::Duration};
 
-use crate::{args::Args, config::Config, serve::serve_req};
+use crate::{args::Args, collector::BitcoinCollector, config::Config, serve::serve_req};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // parse args, before logging which --verbose configures
+    let args: Args = args::from_env();
+
     // setup logging
     env_logger::init_from_env(
         env_logger::Env::new()
-            .default_filter_or("bitcoin_exporter=info")
+            .default_filter_or(if args.verbose {
+                "bitcoin_exporter=debug"
+            } else {
+                "bitcoin_exporter=info"
+            })
             .default_write_style_or("auto"),
     );
     log::info!("{} v{}", env!("CARGO_BIN_NAME"), env!("CARGO_PKG_VERSION"));
 
-    // parse args
-    let args: Args = args::from_env();
     // parse yaml config
     let config = Config::read(&args.config)?;
     let addr = &config.bind.parse()?;
 
-    // create rpc client
-    let rpc = Arc::new(Client::new(
-        &config.host,
-        Auth::UserPass(config.user, config.password),
-    )?);
+    // create one collector per node
+    let config = Arc::new(config);
+    let collectors: Arc<Vec<_>> = Arc::new(
+        config
+            .nodes
+            .iter()
+            .map(|node| BitcoinCollector::new(node, config.clone()))
+            .collect(),
+    );
+    let registry = Registry::new();
+    for collector in collectors.iter() {
+        registry.register(Box::new(collector.clone()))?;
+    }
+
+    for collector in collectors.iter() {
+        // refresh metrics in the background
+        tokio::spawn(collector::run(
+            collector.clone(),
+            Duration::from_secs(config.refresh_interval),
+        ));
+
+        // utxo set statistics are too slow for the regular refresh
+        if let Some(txoutset_interval) = config.txoutset_interval {
+            tokio::spawn(collector::run_txoutset(
+                collector.clone(),
+                Duration::from_secs(config.refresh_interval),
+                Duration::from_secs(txoutset_interval),
+            ));
+        }
+    }
 
     let serve_future = make_service_fn(move |socket: &AddrStream| {
-        let rpc = rpc.clone();
+        let registry = registry.clone();
+        let collectors = collectors.clone();
         let addr = socket.remote_addr();
         async move {
-            Ok::<_, Error>(service_fn(move |req| {
-                let rpc = rpc.clone();
-                serve_req(req, addr, rpc)
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let registry = registry.clone();
+                let collectors = collectors.clone();
+                serve_req(req, addr, registry, collectors)
             }))
         }
     });
@@ -1,137 +1,1181 @@
-use lazy_static::lazy_static;
-use prometheus::{register_counter, register_gauge, register_gauge_vec, Counter, Gauge, GaugeVec};
-
-lazy_static! {
-    pub(crate) static ref BITCOIN_UPTIME: GaugeVec = register_gauge_vec!(
-        "bitcoin_uptime",
-        "Number of seconds the Bitcoin daemon has been running",
-        &["version", "protocol", "chain"]
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_BLOCKS: Gauge = register_gauge!("bitcoin_blocks", "Block height").unwrap();
-    pub(crate) static ref BITCOIN_DIFFICULTY: Gauge =
-        register_gauge!("bitcoin_difficulty", "Difficulty").unwrap();
-    pub(crate) static ref BITCOIN_PEERS: Gauge = register_gauge!("bitcoin_peers", "Number of peers").unwrap();
-    pub(crate) static ref BITCOIN_CONN_IN: Gauge =
-        register_gauge!("bitcoin_conn_in", "Number of connections in").unwrap();
-    pub(crate) static ref BITCOIN_CONN_OUT: Gauge =
-        register_gauge!("bitcoin_conn_out", "Number of connections out").unwrap();
-    pub(crate) static ref BITCOIN_WARNINGS: Counter = register_counter!(
-        "bitcoin_warnings",
-        "Number of network or blockchain warnings detected"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_MEMPOOL_BYTES: Gauge =
-        register_gauge!("bitcoin_mempool_bytes", "Size of mempool in bytes").unwrap();
-    pub(crate) static ref BITCOIN_MEMPOOL_SIZE: Gauge = register_gauge!(
-        "bitcoin_mempool_size",
-        "Number of unconfirmed transactions in mempool"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_MEMPOOL_USAGE: Gauge = register_gauge!(
-        "bitcoin_mempool_usage",
-        "Total memory usage for the mempool"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_MEMPOOL_UNBROADCAST: Gauge = register_gauge!(
-        "bitcoin_mempool_unbroadcast",
-        "Number of transactions waiting for acknowledgment"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_HEIGHT: Gauge = register_gauge!(
-        "bitcoin_latest_block_height",
-        "Height or index of latest block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_WEIGHT: Gauge = register_gauge!(
-        "bitcoin_latest_block_weight",
-        "Weight of latest block according to BIP 141"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_SIZE: Gauge =
-        register_gauge!("bitcoin_latest_block_size", "Size of latest block in bytes").unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_TXS: Gauge = register_gauge!(
-        "bitcoin_latest_block_txs",
-        "Number of transactions in latest block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_NUM_CHAINTIPS: Gauge = register_gauge!(
-        "bitcoin_num_chaintips",
-        "Number of known blockchain branches"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_TOTAL_BYTES_RECV: Gauge =
-        register_gauge!("bitcoin_total_bytes_recv", "Total bytes received").unwrap();
-    pub(crate) static ref BITCOIN_TOTAL_BYTES_SENT: Gauge =
-        register_gauge!("bitcoin_total_bytes_sent", "Total bytes sent").unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_INPUTS: Gauge = register_gauge!(
-        "bitcoin_latest_block_inputs",
-        "Number of inputs in transactions of latest block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_OUTPUTS: Gauge = register_gauge!(
-        "bitcoin_latest_block_outputs",
-        "Number of outputs in transactions of latest block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_VALUE: Gauge = register_gauge!(
-        "bitcoin_latest_block_value",
-        "Bitcoin value of all transactions in the latest block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_LATEST_BLOCK_FEE: Gauge = register_gauge!(
-        "bitcoin_latest_block_fee",
-        "Total fee to process the latest block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_BAN_CREATED: GaugeVec = register_gauge_vec!(
-        "bitcoin_ban_created",
-        "Time the ban was created",
-        &["address", "reason"]
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_BANNED_UNTIL: GaugeVec = register_gauge_vec!(
-        "bitcoin_banned_until",
-        "Time the ban expires",
-        &["address", "reason"]
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_SIZE_ON_DISK: Gauge = register_gauge!(
-        "bitcoin_size_on_disk",
-        "Estimated size of the block and undo files"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_VERIFICATION_PROGRESS: Gauge = register_gauge!(
-        "bitcoin_verification_progress",
-        "Estimate of verification progress [0..1]"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_HASHPS_1: Gauge = register_gauge!(
-        "bitcoin_hashps_1",
-        "Estimated network hash rate per second for the last block"
-    )
-    .unwrap();
-    pub(crate) static ref BITCOIN_HASHPS: Gauge = register_gauge!(
-        "bitcoin_hashps",
-        "Estimated network hash rate per second for the last 120 blocks"
-    )
-    .unwrap();
-
-    // static definition for now (TODO: use const expression)
-    pub(crate) static ref SMART_FEE_2: Gauge = register_gauge!(
-        "bitcoin_est_smart_fee_2",
-        "Estimated smart fee per kilobyte for confirmation in 2 blocks"
-    ).unwrap();
-    pub(crate) static ref SMART_FEE_3: Gauge = register_gauge!(
-        "bitcoin_est_smart_fee_3",
-        "Estimated smart fee per kilobyte for confirmation in 3 blocks"
-    ).unwrap();
-    pub(crate) static ref SMART_FEE_5: Gauge = register_gauge!(
-        "bitcoin_est_smart_fee_5",
-        "Estimated smart fee per kilobyte for confirmation in 5 blocks"
-    ).unwrap();
-    pub(crate) static ref SMART_FEE_20: Gauge = register_gauge!(
-        "bitcoin_est_smart_fee_20",
-        "Estimated smart fee per kilobyte for confirmation in 20 blocks"
-    ).unwrap();
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    register_counter_vec_with_registry, register_counter_with_registry,
+    register_gauge_vec_with_registry, register_gauge_with_registry, Counter, CounterVec, Gauge,
+    GaugeVec, Histogram, HistogramOpts, HistogramVec, Opts, Registry,
+};
+
+/// Exporter self-metrics of a node, kept across refreshes
+#[derive(Clone)]
+pub(crate) struct ExporterMetrics {
+    pub(crate) last_refresh: Gauge,
+    pub(crate) rpc_errors: CounterVec,
+    pub(crate) rpc_duration: HistogramVec,
+}
+
+impl ExporterMetrics {
+    pub(crate) fn new(node: &str) -> prometheus::Result<Self> {
+        Ok(ExporterMetrics {
+            last_refresh: Gauge::with_opts(
+                Opts::new(
+                    "bitcoin_exporter_last_refresh_timestamp_seconds",
+                    "Unix timestamp of the last metrics refresh",
+                )
+                .const_label("node", node),
+            )?,
+            rpc_errors: CounterVec::new(
+                Opts::new(
+                    "bitcoin_exporter_rpc_errors_total",
+                    "Number of failed rpc calls to bitcoind",
+                )
+                .const_label("node", node),
+                &["method"],
+            )?,
+            rpc_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "bitcoin_exporter_rpc_duration_seconds",
+                    "Duration of rpc calls to bitcoind",
+                )
+                .const_label("node", node),
+                &["method"],
+            )?,
+        })
+    }
+
+    pub(crate) fn desc(&self) -> Vec<&Desc> {
+        let mut desc = self.last_refresh.desc();
+        desc.extend(self.rpc_errors.desc());
+        desc.extend(self.rpc_duration.desc());
+        desc
+    }
+
+    pub(crate) fn collect(&self) -> Vec<MetricFamily> {
+        let mut metric_families = self.last_refresh.collect();
+        metric_families.extend(self.rpc_errors.collect());
+        metric_families.extend(self.rpc_duration.collect());
+        metric_families
+    }
+}
+
+/// Bitcoind availability, always exported
+pub(crate) struct StatusMetrics {
+    pub(crate) up: Gauge,
+    pub(crate) uptime: GaugeVec,
+}
+
+impl StatusMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(StatusMetrics {
+            up: register_gauge_with_registry!(
+                "bitcoin_up",
+                "Whether the bitcoind rpc server answered (1) or not (0)",
+                registry
+            )?,
+            uptime: register_gauge_vec_with_registry!(
+                "bitcoin_uptime",
+                "Number of seconds the Bitcoin daemon has been running",
+                &["version", "protocol", "chain"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getblockchaininfo`
+pub(crate) struct BlockchainMetrics {
+    pub(crate) blocks: Gauge,
+    pub(crate) difficulty: Gauge,
+    pub(crate) size_on_disk: Gauge,
+    pub(crate) verification_progress: Gauge,
+    pub(crate) headers: Gauge,
+    pub(crate) blocks_behind_headers: Gauge,
+    pub(crate) initial_block_download: Gauge,
+    pub(crate) pruned: Gauge,
+    pub(crate) median_time: Gauge,
+    pub(crate) chainwork: Gauge,
+}
+
+impl BlockchainMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(BlockchainMetrics {
+            blocks: register_gauge_with_registry!("bitcoin_blocks", "Block height", registry)?,
+            difficulty: register_gauge_with_registry!(
+                "bitcoin_difficulty",
+                "Difficulty",
+                registry
+            )?,
+            size_on_disk: register_gauge_with_registry!(
+                "bitcoin_size_on_disk",
+                "Estimated size of the block and undo files",
+                registry
+            )?,
+            verification_progress: register_gauge_with_registry!(
+                "bitcoin_verification_progress",
+                "Estimate of verification progress [0..1]",
+                registry
+            )?,
+            headers: register_gauge_with_registry!(
+                "bitcoin_headers",
+                "Number of validated headers",
+                registry
+            )?,
+            blocks_behind_headers: register_gauge_with_registry!(
+                "bitcoin_blocks_behind_headers",
+                "Number of validated headers whose block is not yet connected",
+                registry
+            )?,
+            initial_block_download: register_gauge_with_registry!(
+                "bitcoin_initial_block_download",
+                "Whether the node is in initial block download",
+                registry
+            )?,
+            pruned: register_gauge_with_registry!(
+                "bitcoin_pruned",
+                "Whether the blocks are subject to pruning",
+                registry
+            )?,
+            median_time: register_gauge_with_registry!(
+                "bitcoin_mediantime_seconds",
+                "Median time of the past 11 blocks",
+                registry
+            )?,
+            chainwork: register_gauge_with_registry!(
+                "bitcoin_chainwork_log2",
+                "Base 2 logarithm of the total amount of work in the active chain",
+                registry
+            )?,
+        })
+    }
+
+    /// Only reported by pruned nodes
+    pub(crate) fn prune_height(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_prune_height",
+            "Lowest height of complete blocks stored",
+            registry
+        )
+    }
+
+    /// Only reported by nodes pruning automatically
+    pub(crate) fn prune_target_size(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_prune_target_size",
+            "Target size of the block and undo files when pruning automatically",
+            registry
+        )
+    }
+
+    /// Only reported since Bitcoin Core v23
+    pub(crate) fn time(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_time_seconds",
+            "Timestamp of the best block",
+            registry
+        )
+    }
+}
+
+/// Metrics from `getnetworkinfo`
+pub(crate) struct NetworkMetrics {
+    pub(crate) peers: Gauge,
+    pub(crate) conn_in: Gauge,
+    pub(crate) conn_out: Gauge,
+    pub(crate) warnings_active: Gauge,
+    pub(crate) warning_info: GaugeVec,
+    pub(crate) network_reachable: GaugeVec,
+    pub(crate) network_limited: GaugeVec,
+    pub(crate) network_proxy: GaugeVec,
+    pub(crate) local_address_score: GaugeVec,
+    pub(crate) relay_fee: Gauge,
+    pub(crate) incremental_fee: Gauge,
+    pub(crate) local_relay: Gauge,
+    pub(crate) network_active: Gauge,
+    pub(crate) local_service: GaugeVec,
+}
+
+impl NetworkMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(NetworkMetrics {
+            peers: register_gauge_with_registry!("bitcoin_peers", "Number of peers", registry)?,
+            conn_in: register_gauge_with_registry!(
+                "bitcoin_conn_in",
+                "Number of connections in",
+                registry
+            )?,
+            conn_out: register_gauge_with_registry!(
+                "bitcoin_conn_out",
+                "Number of connections out",
+                registry
+            )?,
+            warnings_active: register_gauge_with_registry!(
+                "bitcoin_warnings_active",
+                "Number of network or blockchain warnings currently reported",
+                registry
+            )?,
+            warning_info: register_gauge_vec_with_registry!(
+                "bitcoin_warning_info",
+                "Network or blockchain warning currently reported, always 1",
+                &["message"],
+                registry
+            )?,
+            network_reachable: register_gauge_vec_with_registry!(
+                "bitcoin_network_reachable",
+                "Whether connections to the network are possible",
+                &["network"],
+                registry
+            )?,
+            network_limited: register_gauge_vec_with_registry!(
+                "bitcoin_network_limited",
+                "Whether connections to the network are limited with -onlynet",
+                &["network"],
+                registry
+            )?,
+            network_proxy: register_gauge_vec_with_registry!(
+                "bitcoin_network_proxy_enabled",
+                "Whether the network is reached through a proxy",
+                &["network"],
+                registry
+            )?,
+            local_address_score: register_gauge_vec_with_registry!(
+                "bitcoin_local_address_score",
+                "Relative score of the local address",
+                &["address", "port"],
+                registry
+            )?,
+            relay_fee: register_gauge_with_registry!(
+                "bitcoin_relay_fee_sat_per_kvb",
+                "Minimum relay fee rate in satoshis per kilo virtual byte",
+                registry
+            )?,
+            incremental_fee: register_gauge_with_registry!(
+                "bitcoin_incremental_fee_sat_per_kvb",
+                "Minimum fee rate increment for mempool limiting or replacement in satoshis per kilo virtual byte",
+                registry
+            )?,
+            local_relay: register_gauge_with_registry!(
+                "bitcoin_local_relay",
+                "Whether transactions are requested from peers",
+                registry
+            )?,
+            network_active: register_gauge_with_registry!(
+                "bitcoin_network_active",
+                "Whether p2p networking is enabled",
+                registry
+            )?,
+            local_service: register_gauge_vec_with_registry!(
+                "bitcoin_local_service",
+                "Service offered by the node to its peers, always 1",
+                &["service"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Averages of `getblockstats` over rolling windows of the last blocks
+pub(crate) struct BlockStatsWindowMetrics {
+    pub(crate) blocks: GaugeVec,
+    pub(crate) txs: GaugeVec,
+    pub(crate) avg_fee_rate: GaugeVec,
+    pub(crate) fee_rate_percentile: GaugeVec,
+    pub(crate) weight_utilization: GaugeVec,
+    pub(crate) segwit_ratio: GaugeVec,
+    pub(crate) utxo_increase: GaugeVec,
+    pub(crate) utxo_size_inc: GaugeVec,
+}
+
+impl BlockStatsWindowMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(BlockStatsWindowMetrics {
+            blocks: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_window_blocks",
+                "Number of blocks with statistics in the window",
+                &["window"],
+                registry
+            )?,
+            txs: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_txs_avg",
+                "Average number of transactions per block",
+                &["window"],
+                registry
+            )?,
+            avg_fee_rate: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_avg_fee_rate_sat_per_vb",
+                "Average of the blocks average fee rate in satoshis per virtual byte",
+                &["window"],
+                registry
+            )?,
+            fee_rate_percentile: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_fee_rate_percentile_sat_per_vb",
+                "Average of the blocks fee rate percentiles in satoshis per virtual byte",
+                &["window", "percentile"],
+                registry
+            )?,
+            weight_utilization: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_weight_utilization",
+                "Ratio of the blocks weight to the maximum block weight [0..1]",
+                &["window"],
+                registry
+            )?,
+            segwit_ratio: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_segwit_tx_ratio",
+                "Ratio of segwit transactions, excluding coinbases [0..1]",
+                &["window"],
+                registry
+            )?,
+            utxo_increase: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_utxo_increase",
+                "Change in the number of unspent outputs over the window",
+                &["window"],
+                registry
+            )?,
+            utxo_size_inc: register_gauge_vec_with_registry!(
+                "bitcoin_blockstats_utxo_size_inc",
+                "Change in the size of the utxo set over the window",
+                &["window"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Chain reorganizations seen across refreshes
+pub(crate) struct ReorgMetrics {
+    pub(crate) reorgs: Counter,
+    pub(crate) last_reorg_depth: Gauge,
+}
+
+impl ReorgMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(ReorgMetrics {
+            reorgs: register_counter_with_registry!(
+                "bitcoin_reorgs_total",
+                "Number of chain reorganizations seen by the exporter",
+                registry
+            )?,
+            last_reorg_depth: register_gauge_with_registry!(
+                "bitcoin_last_reorg_depth",
+                "Number of blocks disconnected by the last chain reorganization",
+                registry
+            )?,
+        })
+    }
+}
+
+/// Best block changes observed by the exporter
+pub(crate) struct BlockTimeMetrics {
+    pub(crate) seconds_since_last_block: Gauge,
+}
+
+impl BlockTimeMetrics {
+    /// Registers `block_intervals`, kept across refreshes, along with the refreshed metrics
+    pub(crate) fn new(
+        registry: &Registry,
+        block_intervals: &Histogram,
+    ) -> prometheus::Result<Self> {
+        registry.register(Box::new(block_intervals.clone()))?;
+        Ok(BlockTimeMetrics {
+            seconds_since_last_block: register_gauge_with_registry!(
+                "bitcoin_seconds_since_last_block",
+                "Seconds since the exporter saw the best block change",
+                registry
+            )?,
+        })
+    }
+
+    pub(crate) fn block_intervals() -> prometheus::Result<Histogram> {
+        Histogram::with_opts(
+            HistogramOpts::new(
+                "bitcoin_block_interval_seconds",
+                "Intervals between best block changes seen by the exporter",
+            )
+            .buckets(vec![
+                60.0, 120.0, 300.0, 600.0, 900.0, 1200.0, 1800.0, 2700.0, 3600.0, 5400.0, 7200.0,
+            ]),
+        )
+    }
+}
+
+/// Metrics from `getblockstats` of the best block
+pub(crate) struct LatestBlockMetrics {
+    pub(crate) height: Gauge,
+    pub(crate) weight: Gauge,
+    pub(crate) size: Gauge,
+    pub(crate) txs: Gauge,
+    pub(crate) inputs: Gauge,
+    pub(crate) outputs: Gauge,
+    pub(crate) value: Gauge,
+    pub(crate) fee: Gauge,
+    pub(crate) fee_rate_percentile: GaugeVec,
+    pub(crate) avg_fee_rate: Gauge,
+    pub(crate) min_fee_rate: Gauge,
+    pub(crate) max_fee_rate: Gauge,
+    pub(crate) median_fee: Gauge,
+    pub(crate) segwit_txs: Gauge,
+    pub(crate) segwit_size: Gauge,
+    pub(crate) utxo_increase: Gauge,
+    pub(crate) utxo_size_inc: Gauge,
+    pub(crate) subsidy: Gauge,
+}
+
+impl LatestBlockMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(LatestBlockMetrics {
+            height: register_gauge_with_registry!(
+                "bitcoin_latest_block_height",
+                "Height or index of latest block",
+                registry
+            )?,
+            weight: register_gauge_with_registry!(
+                "bitcoin_latest_block_weight",
+                "Weight of latest block according to BIP 141",
+                registry
+            )?,
+            size: register_gauge_with_registry!(
+                "bitcoin_latest_block_size",
+                "Size of latest block in bytes",
+                registry
+            )?,
+            txs: register_gauge_with_registry!(
+                "bitcoin_latest_block_txs",
+                "Number of transactions in latest block",
+                registry
+            )?,
+            inputs: register_gauge_with_registry!(
+                "bitcoin_latest_block_inputs",
+                "Number of inputs in transactions of latest block",
+                registry
+            )?,
+            outputs: register_gauge_with_registry!(
+                "bitcoin_latest_block_outputs",
+                "Number of outputs in transactions of latest block",
+                registry
+            )?,
+            value: register_gauge_with_registry!(
+                "bitcoin_latest_block_value",
+                "Bitcoin value of all transactions in the latest block",
+                registry
+            )?,
+            fee: register_gauge_with_registry!(
+                "bitcoin_latest_block_fee",
+                "Total fee to process the latest block",
+                registry
+            )?,
+            fee_rate_percentile: register_gauge_vec_with_registry!(
+                "bitcoin_latest_block_fee_rate_percentile_sat_per_vb",
+                "Fee rate percentiles of the latest block in satoshis per virtual byte, weighted by size",
+                &["percentile"],
+                registry
+            )?,
+            avg_fee_rate: register_gauge_with_registry!(
+                "bitcoin_latest_block_avg_fee_rate_sat_per_vb",
+                "Average fee rate of the latest block in satoshis per virtual byte",
+                registry
+            )?,
+            min_fee_rate: register_gauge_with_registry!(
+                "bitcoin_latest_block_min_fee_rate_sat_per_vb",
+                "Minimum fee rate of the latest block in satoshis per virtual byte",
+                registry
+            )?,
+            max_fee_rate: register_gauge_with_registry!(
+                "bitcoin_latest_block_max_fee_rate_sat_per_vb",
+                "Maximum fee rate of the latest block in satoshis per virtual byte",
+                registry
+            )?,
+            median_fee: register_gauge_with_registry!(
+                "bitcoin_latest_block_median_fee",
+                "Median fee of the latest block transactions",
+                registry
+            )?,
+            segwit_txs: register_gauge_with_registry!(
+                "bitcoin_latest_block_segwit_txs",
+                "Number of segwit transactions in the latest block",
+                registry
+            )?,
+            segwit_size: register_gauge_with_registry!(
+                "bitcoin_latest_block_segwit_size",
+                "Total size of the segwit transactions in the latest block",
+                registry
+            )?,
+            utxo_increase: register_gauge_with_registry!(
+                "bitcoin_latest_block_utxo_increase",
+                "Change in the number of unspent outputs by the latest block",
+                registry
+            )?,
+            utxo_size_inc: register_gauge_with_registry!(
+                "bitcoin_latest_block_utxo_size_inc",
+                "Change in the size of the utxo set by the latest block",
+                registry
+            )?,
+            subsidy: register_gauge_with_registry!(
+                "bitcoin_latest_block_subsidy",
+                "Block subsidy of the latest block",
+                registry
+            )?,
+        })
+    }
+
+    /// From `getblock`, so it doesn't depend on `getblockstats` succeeding
+    pub(crate) fn timestamp(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_latest_block_timestamp_seconds",
+            "Timestamp of the latest block",
+            registry
+        )
+    }
+
+    /// From `getblock`, which omits it on some versions
+    pub(crate) fn median_time(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_latest_block_mediantime_seconds",
+            "Median time of the 11 blocks up to the latest block",
+            registry
+        )
+    }
+}
+
+/// Metrics from `estimatesmartfee`
+pub(crate) struct SmartFeeMetrics {
+    pub(crate) fee_rate: GaugeVec,
+    pub(crate) blocks: GaugeVec,
+    pub(crate) errors: GaugeVec,
+}
+
+impl SmartFeeMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(SmartFeeMetrics {
+            fee_rate: register_gauge_vec_with_registry!(
+                "bitcoin_est_smart_fee_sat_per_kvb",
+                "Estimated smart fee rate in satoshis per kilo virtual byte for confirmation within target blocks",
+                &["target", "mode"],
+                registry
+            )?,
+            blocks: register_gauge_vec_with_registry!(
+                "bitcoin_est_smart_fee_blocks",
+                "Number of blocks for which the smart fee estimate was found",
+                &["target", "mode"],
+                registry
+            )?,
+            errors: register_gauge_vec_with_registry!(
+                "bitcoin_est_smart_fee_errors",
+                "Number of errors reported by the smart fee estimator",
+                &["target", "mode"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getnetworkhashps`
+pub(crate) struct HashrateMetrics {
+    pub(crate) hashps: Gauge,
+}
+
+impl HashrateMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(HashrateMetrics {
+            hashps: register_gauge_with_registry!(
+                "bitcoin_hashps",
+                "Estimated network hash rate per second for the last 120 blocks",
+                registry
+            )?,
+        })
+    }
+
+    /// From a separate `getnetworkhashps` call, so registered on its own
+    pub(crate) fn hashps_1(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_hashps_1",
+            "Estimated network hash rate per second for the last block",
+            registry
+        )
+    }
+}
+
+/// Metrics from `listbanned`
+pub(crate) struct BanMetrics {
+    pub(crate) banned_peers: Gauge,
+    pub(crate) ban_created: GaugeVec,
+    pub(crate) banned_until: GaugeVec,
+    pub(crate) ban_duration: GaugeVec,
+    pub(crate) ban_time_remaining: GaugeVec,
+}
+
+impl BanMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(BanMetrics {
+            banned_peers: register_gauge_with_registry!(
+                "bitcoin_banned_peers",
+                "Number of banned addresses or subnets",
+                registry
+            )?,
+            ban_created: register_gauge_vec_with_registry!(
+                "bitcoin_ban_created",
+                "Time the ban was created",
+                &["address", "reason"],
+                registry
+            )?,
+            banned_until: register_gauge_vec_with_registry!(
+                "bitcoin_banned_until",
+                "Time the ban expires",
+                &["address", "reason"],
+                registry
+            )?,
+            ban_duration: register_gauge_vec_with_registry!(
+                "bitcoin_ban_duration_seconds",
+                "Duration of the ban in seconds",
+                &["address", "reason"],
+                registry
+            )?,
+            ban_time_remaining: register_gauge_vec_with_registry!(
+                "bitcoin_ban_time_remaining_seconds",
+                "Seconds remaining until the ban expires",
+                &["address", "reason"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getchaintips`
+pub(crate) struct ChainTipsMetrics {
+    pub(crate) num_chaintips: Gauge,
+    pub(crate) chaintips: GaugeVec,
+    pub(crate) fork_branch_length: Gauge,
+    pub(crate) fork_height: Gauge,
+}
+
+impl ChainTipsMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(ChainTipsMetrics {
+            num_chaintips: register_gauge_with_registry!(
+                "bitcoin_num_chaintips",
+                "Number of known blockchain branches",
+                registry
+            )?,
+            chaintips: register_gauge_vec_with_registry!(
+                "bitcoin_chaintips",
+                "Number of known blockchain branches by status",
+                &["status"],
+                registry
+            )?,
+            fork_branch_length: register_gauge_with_registry!(
+                "bitcoin_longest_fork_branch_length",
+                "Number of blocks of the longest branch not part of the active chain",
+                registry
+            )?,
+            fork_height: register_gauge_with_registry!(
+                "bitcoin_longest_fork_height",
+                "Height of the tip of the longest branch not part of the active chain",
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getmempoolinfo`
+pub(crate) struct MempoolMetrics {
+    pub(crate) bytes: Gauge,
+    pub(crate) size: Gauge,
+    pub(crate) usage: Gauge,
+    pub(crate) unbroadcast: Gauge,
+    pub(crate) max_bytes: Gauge,
+    pub(crate) min_fee: Gauge,
+    pub(crate) min_relay_tx_fee: Gauge,
+}
+
+impl MempoolMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(MempoolMetrics {
+            bytes: register_gauge_with_registry!(
+                "bitcoin_mempool_bytes",
+                "Size of mempool in bytes",
+                registry
+            )?,
+            size: register_gauge_with_registry!(
+                "bitcoin_mempool_size",
+                "Number of unconfirmed transactions in mempool",
+                registry
+            )?,
+            usage: register_gauge_with_registry!(
+                "bitcoin_mempool_usage",
+                "Total memory usage for the mempool",
+                registry
+            )?,
+            unbroadcast: register_gauge_with_registry!(
+                "bitcoin_mempool_unbroadcast",
+                "Number of transactions waiting for acknowledgment",
+                registry
+            )?,
+            max_bytes: register_gauge_with_registry!(
+                "bitcoin_mempool_max_bytes",
+                "Maximum memory usage for the mempool",
+                registry
+            )?,
+            min_fee: register_gauge_with_registry!(
+                "bitcoin_mempool_min_fee_sat_per_kvb",
+                "Minimum fee rate in satoshis per kilo virtual byte for a transaction to be accepted in the mempool",
+                registry
+            )?,
+            min_relay_tx_fee: register_gauge_with_registry!(
+                "bitcoin_mempool_min_relay_tx_fee_sat_per_kvb",
+                "Minimum relay fee rate in satoshis per kilo virtual byte",
+                registry
+            )?,
+        })
+    }
+
+    /// Missing from older Bitcoin Core versions
+    pub(crate) fn total_fee(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mempool_total_fee",
+            "Total fee of the mempool transactions, ignoring prioritisetransaction",
+            registry
+        )
+    }
+
+    /// Missing from older Bitcoin Core versions
+    pub(crate) fn incremental_relay_fee(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mempool_incremental_relay_fee_sat_per_kvb",
+            "Minimum fee rate increment for mempool limiting or replacement in satoshis per kilo virtual byte",
+            registry
+        )
+    }
+
+    /// Added in Bitcoin Core v0.19
+    pub(crate) fn loaded(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mempool_loaded",
+            "Whether the mempool is fully loaded from disk",
+            registry
+        )
+    }
+
+    /// Only reported by versions with the `mempoolfullrbf` option
+    pub(crate) fn full_rbf(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mempool_full_rbf",
+            "Whether the mempool accepts replacements without signaling",
+            registry
+        )
+    }
+}
+
+/// Metrics from `getrawmempool true`
+pub(crate) struct MempoolFeeMetrics {
+    pub(crate) txs: GaugeVec,
+    pub(crate) vsize: GaugeVec,
+}
+
+impl MempoolFeeMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(MempoolFeeMetrics {
+            txs: register_gauge_vec_with_registry!(
+                "bitcoin_mempool_txs_by_fee_rate",
+                "Number of mempool transactions paying a fee rate lower than or equal to le sat/vB",
+                &["le"],
+                registry
+            )?,
+            vsize: register_gauge_vec_with_registry!(
+                "bitcoin_mempool_vsize_by_fee_rate",
+                "Virtual size of mempool transactions paying a fee rate lower than or equal to le sat/vB",
+                &["le"],
+                registry
+            )?,
+        })
+    }
+
+    /// Registered whether or not the distribution is exported, so a skipped one is visible
+    pub(crate) fn skipped(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mempool_fee_distribution_skipped",
+            "Whether the mempool fee rate distribution was skipped as the mempool holds more than mempool_max_txs transactions",
+            registry
+        )
+    }
+}
+
+/// Peer counts from `getpeerinfo`
+pub(crate) struct PeerMetrics {
+    pub(crate) peers: GaugeVec,
+    pub(crate) versions: GaugeVec,
+    pub(crate) net_bytes: CounterVec,
+}
+
+impl PeerMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(PeerMetrics {
+            peers: register_gauge_vec_with_registry!(
+                "bitcoin_peers_by_type",
+                "Number of connected peers by connection type, network and transport protocol",
+                &["connection_type", "network", "transport_protocol_type"],
+                registry
+            )?,
+            versions: register_gauge_vec_with_registry!(
+                "bitcoin_peers_by_version",
+                "Number of connected peers by client and major version of their user agent",
+                &["subversion"],
+                registry
+            )?,
+            net_bytes: register_counter_vec_with_registry!(
+                "bitcoin_net_bytes_total",
+                "Bytes exchanged with peers by direction and message type",
+                &["direction", "msg_type"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Per peer metrics from `getpeerinfo`
+pub(crate) struct PeerDetailMetrics {
+    pub(crate) ping: GaugeVec,
+    pub(crate) min_ping: GaugeVec,
+    pub(crate) bytes_sent: GaugeVec,
+    pub(crate) bytes_recv: GaugeVec,
+    pub(crate) conn_time: GaugeVec,
+    pub(crate) synced_headers: GaugeVec,
+    pub(crate) synced_blocks: GaugeVec,
+}
+
+impl PeerDetailMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(PeerDetailMetrics {
+            ping: register_gauge_vec_with_registry!(
+                "bitcoin_peer_ping_seconds",
+                "Last ping time of the peer",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+            min_ping: register_gauge_vec_with_registry!(
+                "bitcoin_peer_min_ping_seconds",
+                "Minimum observed ping time of the peer",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+            bytes_sent: register_gauge_vec_with_registry!(
+                "bitcoin_peer_bytes_sent",
+                "Total bytes sent to the peer",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+            bytes_recv: register_gauge_vec_with_registry!(
+                "bitcoin_peer_bytes_recv",
+                "Total bytes received from the peer",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+            conn_time: register_gauge_vec_with_registry!(
+                "bitcoin_peer_connection_time_seconds",
+                "Connection time of the peer as a UNIX timestamp",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+            synced_headers: register_gauge_vec_with_registry!(
+                "bitcoin_peer_synced_headers",
+                "Last header height in common with the peer",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+            synced_blocks: register_gauge_vec_with_registry!(
+                "bitcoin_peer_synced_blocks",
+                "Last block height in common with the peer",
+                &["addr", "connection_type", "network"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getdeploymentinfo`, or the `softforks` of `getblockchaininfo` before v23
+pub(crate) struct DeploymentMetrics {
+    pub(crate) active: GaugeVec,
+    pub(crate) height: GaugeVec,
+    pub(crate) bip9_status: GaugeVec,
+    pub(crate) bip9_since: GaugeVec,
+    pub(crate) bip9_period: GaugeVec,
+    pub(crate) bip9_threshold: GaugeVec,
+    pub(crate) bip9_elapsed: GaugeVec,
+    pub(crate) bip9_count: GaugeVec,
+}
+
+impl DeploymentMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(DeploymentMetrics {
+            active: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_active",
+                "Whether the deployment rules are enforced for the next block",
+                &["name", "type"],
+                registry
+            )?,
+            height: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_height",
+                "Height of the first block the deployment rules apply to",
+                &["name", "type"],
+                registry
+            )?,
+            bip9_status: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_bip9_status",
+                "Current bip9 status of the deployment, always 1",
+                &["name", "status"],
+                registry
+            )?,
+            bip9_since: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_bip9_since",
+                "Height of the first block the current bip9 status applies to",
+                &["name"],
+                registry
+            )?,
+            bip9_period: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_bip9_period",
+                "Length in blocks of the bip9 signalling period",
+                &["name"],
+                registry
+            )?,
+            bip9_threshold: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_bip9_threshold",
+                "Number of signalling blocks required in a period for activation",
+                &["name"],
+                registry
+            )?,
+            bip9_elapsed: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_bip9_elapsed",
+                "Number of blocks elapsed since the beginning of the current period",
+                &["name"],
+                registry
+            )?,
+            bip9_count: register_gauge_vec_with_registry!(
+                "bitcoin_deployment_bip9_count",
+                "Number of signalling blocks in the current period",
+                &["name"],
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getblocktemplate`
+pub(crate) struct BlockTemplateMetrics {
+    pub(crate) txs: Gauge,
+    pub(crate) fees: Gauge,
+    pub(crate) weight: Gauge,
+    pub(crate) sigops: Gauge,
+    pub(crate) coinbase_value: Gauge,
+    pub(crate) height: Gauge,
+    pub(crate) generation: Gauge,
+}
+
+impl BlockTemplateMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(BlockTemplateMetrics {
+            txs: register_gauge_with_registry!(
+                "bitcoin_block_template_txs",
+                "Number of transactions in the block template",
+                registry
+            )?,
+            fees: register_gauge_with_registry!(
+                "bitcoin_block_template_fees",
+                "Total fee of the block template transactions",
+                registry
+            )?,
+            weight: register_gauge_with_registry!(
+                "bitcoin_block_template_weight",
+                "Total weight of the block template transactions, excluding the coinbase",
+                registry
+            )?,
+            sigops: register_gauge_with_registry!(
+                "bitcoin_block_template_sigops",
+                "Total signature operations of the block template transactions",
+                registry
+            )?,
+            coinbase_value: register_gauge_with_registry!(
+                "bitcoin_block_template_coinbase_value",
+                "Maximum value of the block template coinbase",
+                registry
+            )?,
+            height: register_gauge_with_registry!(
+                "bitcoin_block_template_height",
+                "Height of the block template",
+                registry
+            )?,
+            generation: register_gauge_with_registry!(
+                "bitcoin_block_template_generation_seconds",
+                "Time taken by bitcoind to build the block template",
+                registry
+            )?,
+        })
+    }
+}
+
+/// Metrics from `getmininginfo`
+pub(crate) struct MiningMetrics {
+    pub(crate) pooled_txs: Gauge,
+}
+
+impl MiningMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(MiningMetrics {
+            pooled_txs: register_gauge_with_registry!(
+                "bitcoin_mining_pooled_txs",
+                "Number of transactions in the mempool",
+                registry
+            )?,
+        })
+    }
+
+    /// Only reported once bitcoind assembled a block template
+    pub(crate) fn current_block_weight(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mining_current_block_weight",
+            "Weight of the last assembled block template",
+            registry
+        )
+    }
+
+    /// Only reported once bitcoind assembled a block template
+    pub(crate) fn current_block_txs(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_mining_current_block_txs",
+            "Number of transactions of the last assembled block template",
+            registry
+        )
+    }
+
+    /// Needs both `getmininginfo` and `getblocktemplate`
+    pub(crate) fn template_blocks_behind(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_block_template_blocks_behind",
+            "Number of blocks the block template is behind the next block height, 0 when up to date",
+            registry
+        )
+    }
+}
+
+/// Metrics from `gettxoutsetinfo`, refreshed on their own schedule
+pub(crate) struct TxOutSetMetrics {
+    pub(crate) height: Gauge,
+    pub(crate) txouts: Gauge,
+    pub(crate) bogosize: Gauge,
+    pub(crate) total_amount: Gauge,
+    pub(crate) last_update: Gauge,
+}
+
+impl TxOutSetMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(TxOutSetMetrics {
+            height: register_gauge_with_registry!(
+                "bitcoin_txoutset_height",
+                "Height of the block the utxo set statistics were computed at",
+                registry
+            )?,
+            txouts: register_gauge_with_registry!(
+                "bitcoin_txoutset_txouts",
+                "Number of unspent transaction outputs",
+                registry
+            )?,
+            bogosize: register_gauge_with_registry!(
+                "bitcoin_txoutset_bogosize",
+                "Database independent metric of the utxo set size",
+                registry
+            )?,
+            total_amount: register_gauge_with_registry!(
+                "bitcoin_txoutset_total_amount",
+                "Total amount of coins in the utxo set",
+                registry
+            )?,
+            last_update: register_gauge_with_registry!(
+                "bitcoin_txoutset_last_update_timestamp_seconds",
+                "Unix timestamp of the last utxo set statistics update",
+                registry
+            )?,
+        })
+    }
+
+    /// Not reported when using the coinstatsindex
+    pub(crate) fn transactions(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_txoutset_transactions",
+            "Number of transactions with unspent outputs",
+            registry
+        )
+    }
+
+    /// Not reported when using the coinstatsindex
+    pub(crate) fn disk_size(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_txoutset_disk_size",
+            "Estimated size of the chainstate on disk",
+            registry
+        )
+    }
+
+    /// Only reported when using the coinstatsindex
+    pub(crate) fn total_unspendable_amount(registry: &Registry) -> prometheus::Result<Gauge> {
+        register_gauge_with_registry!(
+            "bitcoin_txoutset_total_unspendable_amount",
+            "Total amount of coins permanently excluded from the utxo set",
+            registry
+        )
+    }
+}
+
+/// Metrics from `getnettotals`
+pub(crate) struct NetTotalsMetrics {
+    pub(crate) total_bytes_recv: Gauge,
+    pub(crate) total_bytes_sent: Gauge,
+    pub(crate) upload_target_timeframe: Gauge,
+    pub(crate) upload_target: Gauge,
+    pub(crate) upload_target_reached: Gauge,
+    pub(crate) serve_historical_blocks: Gauge,
+    pub(crate) bytes_left_in_cycle: Gauge,
+    pub(crate) time_left_in_cycle: Gauge,
+}
+
+impl NetTotalsMetrics {
+    pub(crate) fn new(registry: &Registry) -> prometheus::Result<Self> {
+        Ok(NetTotalsMetrics {
+            total_bytes_recv: register_gauge_with_registry!(
+                "bitcoin_total_bytes_recv",
+                "Total bytes received",
+                registry
+            )?,
+            total_bytes_sent: register_gauge_with_registry!(
+                "bitcoin_total_bytes_sent",
+                "Total bytes sent",
+                registry
+            )?,
+            upload_target_timeframe: register_gauge_with_registry!(
+                "bitcoin_upload_target_timeframe_seconds",
+                "Length of the upload target measuring cycle",
+                registry
+            )?,
+            upload_target: register_gauge_with_registry!(
+                "bitcoin_upload_target_bytes",
+                "Upload target in bytes per cycle, 0 when unlimited",
+                registry
+            )?,
+            upload_target_reached: register_gauge_with_registry!(
+                "bitcoin_upload_target_reached",
+                "Whether the upload target is reached",
+                registry
+            )?,
+            serve_historical_blocks: register_gauge_with_registry!(
+                "bitcoin_upload_target_serve_historical_blocks",
+                "Whether historical blocks are still served",
+                registry
+            )?,
+            bytes_left_in_cycle: register_gauge_with_registry!(
+                "bitcoin_upload_target_bytes_left_in_cycle",
+                "Bytes left in the current upload target cycle",
+                registry
+            )?,
+            time_left_in_cycle: register_gauge_with_registry!(
+                "bitcoin_upload_target_time_left_in_cycle_seconds",
+                "Time left in the current upload target cycle",
+                registry
+            )?,
+        })
+    }
 }
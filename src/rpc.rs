@@ -0,0 +1,55 @@
+use bitcoincore_rpc::{jsonrpc, Auth, Client, Error, Result as ClientResult};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+type Credentials = (Option<String>, Option<String>);
+
+/// Bitcoind rpc client, rebuilt whenever the credentials change.
+///
+/// bitcoind writes a new cookie file on each restart, so the cookie is read again
+/// before every refresh instead of once at startup.
+pub(crate) struct RpcClient {
+    host: String,
+    auth: Auth,
+    timeout: Duration,
+    client: Mutex<Option<(Credentials, Arc<Client>)>>,
+}
+
+impl RpcClient {
+    pub(crate) fn new(host: &str, auth: Auth, timeout: Duration) -> Self {
+        RpcClient {
+            host: host.to_owned(),
+            auth,
+            timeout,
+            client: Mutex::new(None),
+        }
+    }
+
+    /// Get a client using the current credentials
+    pub(crate) fn get(&self) -> ClientResult<Arc<Client>> {
+        let credentials = self.auth.clone().get_user_pass()?;
+        let mut client = self.client.lock().unwrap();
+        match &*client {
+            Some((current, rpc)) if *current == credentials => Ok(rpc.clone()),
+            previous => {
+                if previous.is_some() {
+                    log::info!("rpc credentials changed, reconnecting to {}", self.host);
+                }
+                let (user, pass) = credentials.clone();
+                let mut builder = jsonrpc::simple_http::Builder::new()
+                    .url(&self.host)
+                    .map_err(|e| Error::JsonRpc(e.into()))?
+                    .timeout(self.timeout);
+                if let Some(user) = user {
+                    builder = builder.auth(user, pass);
+                }
+                let rpc = jsonrpc::client::Client::with_transport(builder.build());
+                let rpc = Arc::new(Client::from_jsonrpc(rpc));
+                *client = Some((credentials, rpc.clone()));
+                Ok(rpc)
+            }
+        }
+    }
+}